@@ -1,19 +1,20 @@
-DROP TABLE IF EXISTS account;
-DROP TABLE IF EXISTS friend;
+-- Applied by `loon` each time the db is opened, see `migrate` in src/cmd/db.rs. Columns
+-- added to an existing table must also be added there.
 
-CREATE TABLE account (
+CREATE TABLE IF NOT EXISTS account (
     id INTEGER PRIMARY KEY,
     network TEXT NOT NULL,
     nick TEXT NOT NULL,
-    descriptor BLOB NOT NULL
+    descriptor BLOB NOT NULL,
+    transport TEXT NOT NULL DEFAULT 'note'
 );
 
 -- test 2-of-2 public descriptor
 -- INSERT INTO account (network, nick, descriptor) VALUES ("signet", "test", "wsh(multi(2,[7d94197e/84h/1h/0h]tpubDCmcN1ucMUfxxabEnLKHzUbjaxg8P4YR4V7mMsfhnsdRJquRyDTudrBmzZhrpV4Z4PH3MjKKFtBk6WkJbEWqL9Vc8E8v1tqFxtFXRY8zEjG/<0;1>/*,[9aa5b7ee/84h/1h/0h]tpubDCUB1aBPqtRaVXRpV6WT8RBKn6ZJhua9Uat8vvqfz2gD2zjSaGAasvKMsvcXHhCxrtv9T826vDpYRRhkU8DCRBxMd9Se3dzbScvcguWjcqF/<0;1>/*))");
 
-CREATE TABLE friend (
+CREATE TABLE IF NOT EXISTS friend (
     account_id INTEGER NOT NULL,
-    quorum_id INTEGER NOT NULL,
+    quorum_id INTEGER NOT NULL CHECK (quorum_id BETWEEN 0 AND 65535),
     npub TEXT NOT NULL,
     alias TEXT,
     FOREIGN KEY (account_id) REFERENCES account(id),
@@ -25,3 +26,65 @@ CREATE TABLE friend (
 
 -- Chicken458
 -- INSERT INTO friend (account_id, quorum_id, npub, alias) VALUES (1, 1, "npub100au36unfamj5npttgyce9szdtd3a5vtrwnx7fsqmn4jdu5xnl0qhnm2jh", "chicken");
+
+CREATE TABLE IF NOT EXISTS inbox (
+    event_id TEXT NOT NULL,
+    account_id INTEGER NOT NULL,
+    pid INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    ty TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    read INTEGER NOT NULL DEFAULT 0,
+    expires_at INTEGER,
+    archived INTEGER NOT NULL DEFAULT 0,
+    reply_to TEXT,
+    broadcast INTEGER NOT NULL DEFAULT 0,
+    response TEXT,
+    FOREIGN KEY (account_id) REFERENCES account(id),
+    PRIMARY KEY (account_id, event_id)
+);
+
+CREATE TABLE IF NOT EXISTS hook (
+    id INTEGER PRIMARY KEY,
+    account_id INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    command TEXT,
+    url TEXT,
+    FOREIGN KEY (account_id) REFERENCES account(id)
+);
+
+CREATE TABLE IF NOT EXISTS relay (
+    account_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    FOREIGN KEY (account_id) REFERENCES account(id),
+    PRIMARY KEY (account_id, url)
+);
+
+CREATE TABLE IF NOT EXISTS fetch_cursor (
+    account_id INTEGER NOT NULL,
+    relay TEXT NOT NULL,
+    since INTEGER NOT NULL,
+    FOREIGN KEY (account_id) REFERENCES account(id),
+    PRIMARY KEY (account_id, relay)
+);
+
+CREATE TABLE IF NOT EXISTS seen_nonce (
+    account_id INTEGER NOT NULL,
+    pid INTEGER NOT NULL,
+    nonce TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (account_id) REFERENCES account(id),
+    PRIMARY KEY (account_id, pid, nonce)
+);
+
+CREATE TABLE IF NOT EXISTS chunk (
+    account_id INTEGER NOT NULL,
+    event_id TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    chunk TEXT NOT NULL,
+    received_at INTEGER NOT NULL,
+    done INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (account_id) REFERENCES account(id),
+    PRIMARY KEY (account_id, event_id)
+);
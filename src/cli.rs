@@ -1,6 +1,9 @@
 use bitcoin::{address::NetworkUnchecked, Address};
 use clap::Parser;
 use clap::Subcommand;
+use loon::Transport;
+
+use crate::cmd::hook::HookEvent;
 
 #[derive(Parser)]
 #[clap(author, about, version)]
@@ -8,6 +11,9 @@ pub struct Args {
     /// Account id
     #[clap(long, short)]
     pub account_id: Option<u32>,
+    /// Print output as JSON
+    #[clap(long, global = true)]
+    pub json: bool,
     #[clap(subcommand)]
     pub cmd: Cmd,
 }
@@ -18,6 +24,12 @@ pub enum Cmd {
     #[clap(subcommand)]
     #[cfg(feature = "nostr-sdk")]
     Call(CallSubCmd),
+    /// NIP-44 encrypt and decrypt messages.
+    #[clap(subcommand)]
+    #[cfg(feature = "nostr-sdk")]
+    Crypto(CryptoSubCmd),
+    /// Run in the background, syncing the wallet and polling for notes.
+    Daemon(DaemonOpt),
     /// Database operations.
     #[clap(subcommand)]
     Db(DbSubCmd),
@@ -30,12 +42,30 @@ pub enum Cmd {
         /// Poll for new notes continuously.
         #[clap(long, short = 'l')]
         listen: bool,
+        /// Leave out plain notes that aren't calls.
+        #[clap(long)]
+        calls_only: bool,
     },
     /// Get best block hash
     Hash,
+    /// Inbox of fetched notes.
+    #[clap(subcommand)]
+    #[cfg(feature = "nostr-sdk")]
+    Inbox(InboxSubCmd),
     /// Generate a keypair
     #[clap(subcommand)]
     Generate(GenerateSubCmd),
+    /// Keystore of the nostr identity of each account.
+    #[clap(subcommand)]
+    #[cfg(feature = "nostr-sdk")]
+    Key(KeySubCmd),
+    /// Serve a local HTTP JSON API
+    Serve(ServeOpt),
+    /// Query the status of a running daemon
+    Status,
+    /// Interactive terminal UI
+    #[cfg(feature = "tui")]
+    Tui,
     /// Wallet operations.
     #[clap(subcommand)]
     Wallet(WalletSubCmd),
@@ -59,19 +89,70 @@ pub struct CallOpt {
     #[clap(flatten)]
     pub recipient: Recipient,
     /// Text
-    #[clap(long, short = 'm')]
+    #[clap(long, short = 'm', conflicts_with_all = ["psbt", "ack", "nack"])]
     pub note: Option<String>,
+    /// Base64 encoded PSBT to request signatures for
+    #[clap(long, short = 'p', conflicts_with_all = ["ack", "nack"])]
+    pub psbt: Option<String>,
+    /// Send the PSBT as signed rather than requesting signatures
+    #[clap(long, requires = "psbt")]
+    pub signed: bool,
+    /// Memo describing the PSBT request
+    #[clap(long, requires = "psbt")]
+    pub memo: Option<String>,
     /// Affirmative
-    #[clap(long, short = 'a')]
+    #[clap(long, short = 'a', conflicts_with = "nack")]
     pub ack: bool,
     /// Negative
     #[clap(long, short = 'n')]
     pub nack: bool,
+    /// Reason for a nack
+    #[clap(long, requires = "nack")]
+    pub reason: Option<String>,
+    /// Id of the event being acked or nacked. Defaults to the event replied to
+    #[clap(long)]
+    pub ref_event: Option<String>,
+    /// Id of the event carrying the call this one replies to
+    #[clap(long)]
+    pub reply_to: Option<String>,
+    /// Seconds after which the call expires
+    #[clap(long)]
+    pub ttl: Option<u64>,
     /// Preview a call without sending
     #[clap(long, short = 'd')]
     pub dryrun: bool,
 }
 
+#[derive(Subcommand)]
+#[cfg(feature = "nostr-sdk")]
+pub enum CryptoSubCmd {
+    /// Encrypt a message to a participant.
+    Encrypt {
+        /// Recipient npub or alias
+        #[clap(long, required = true)]
+        to: String,
+        /// Message, read from stdin if not given
+        message: Option<String>,
+    },
+    /// Decrypt a message, or the payload of a call, from a participant.
+    Decrypt {
+        /// Sender npub or alias
+        #[clap(long, required = true)]
+        from: String,
+        /// Ciphertext or call, read from stdin if not given
+        message: Option<String>,
+    },
+}
+
+#[derive(Parser)]
+pub struct DaemonOpt {
+    /// Bitcoind ZMQ endpoint publishing `hashblock` or `rawtx`, e.g. tcp://127.0.0.1:28332.
+    /// May be given more than once.
+    #[cfg(feature = "zmq")]
+    #[clap(long)]
+    pub zmq: Vec<String>,
+}
+
 #[derive(Subcommand)]
 pub enum DescSubCmd {
     /// Import a descriptor to Bitcoin Core
@@ -107,6 +188,9 @@ pub enum DbSubCmd {
         /// Descriptor
         #[clap(required = true)]
         descriptor: String,
+        /// How calls are sent, either "note" or "gift-wrap"
+        #[clap(long, default_value_t)]
+        transport: Transport,
     },
     /// Add new participant to existing quorum
     Friend {
@@ -123,13 +207,103 @@ pub enum DbSubCmd {
         #[clap(required = true)]
         alias: String,
     },
+    /// Set how calls are sent for an existing account
+    Transport {
+        /// Account id
+        #[clap(required = true)]
+        account_id: u32,
+        /// Either "note" or "gift-wrap"
+        #[clap(required = true)]
+        transport: Transport,
+    },
+    /// Add a hook to an existing account, or list its hooks if no event is given
+    Hook {
+        /// Account id
+        #[clap(required = true)]
+        account_id: u32,
+        /// Event running the hook, one of "call", "psbt-request" or "tx"
+        event: Option<HookEvent>,
+        /// Shell command to run with the event as JSON on stdin
+        #[clap(long, requires = "event", conflicts_with = "url")]
+        command: Option<String>,
+        /// Url to POST the event to as JSON, e.g. http://127.0.0.1:8080/loon
+        #[clap(long, requires = "event")]
+        url: Option<String>,
+        /// Remove the hook with this id instead
+        #[clap(long, conflicts_with = "event")]
+        remove: Option<u32>,
+    },
+    /// Add a relay to an existing account, or list its relays if no url is given
+    Relay {
+        /// Account id
+        #[clap(required = true)]
+        account_id: u32,
+        /// Relay url, e.g. wss://relay.damus.io
+        url: Option<String>,
+        /// Remove the relay instead
+        #[clap(long, requires = "url")]
+        remove: bool,
+    },
+}
+
+#[derive(Subcommand)]
+#[cfg(feature = "nostr-sdk")]
+pub enum InboxSubCmd {
+    /// List notes in the inbox, newest first.
+    List {
+        /// Only list unread notes.
+        #[clap(long, short = 'u')]
+        unread: bool,
+        /// List archived notes, i.e. expired calls and PSBT requests whose inputs are spent,
+        /// instead.
+        #[clap(long)]
+        archived: bool,
+    },
+    /// Read a note, marking it as read.
+    Read {
+        /// Event id, or a unique prefix of it
+        #[clap(required = true)]
+        id: String,
+    },
+    /// Delete a note.
+    Delete {
+        /// Event id, or a unique prefix of it
+        #[clap(required = true)]
+        id: String,
+    },
+}
+
+#[derive(Parser)]
+pub struct ServeOpt {
+    /// Port to listen on
+    #[clap(long, short, default_value = "8338")]
+    pub port: u16,
+    /// Path to the API token file, created if it doesn't exist
+    #[clap(long, default_value = "loon-serve.token")]
+    pub token_file: String,
+}
+
+#[derive(Subcommand)]
+#[cfg(feature = "nostr-sdk")]
+pub enum KeySubCmd {
+    /// Generate new nostr keys for the account, encrypted with a passphrase.
+    Generate {
+        /// Replace the existing key of the account
+        #[clap(long)]
+        force: bool,
+    },
+    /// Import the nsec of the account, read from the terminal, encrypted with a passphrase.
+    Import {
+        /// Replace the existing key of the account
+        #[clap(long)]
+        force: bool,
+    },
+    /// Unlock the key of the account and show its npub.
+    Show,
 }
 
 #[derive(Subcommand)]
 pub enum GenerateSubCmd {
-    /// Generate nostr keys
-    #[cfg(feature = "nostr-sdk")]
-    Nsec,
     /// Generate a random WIF private key
     Wif {
         /// Specifies that the key is valid for test networks. If none specified, use mainnet
@@ -218,4 +392,7 @@ pub struct Recipient {
     /// Recipient alias
     #[clap(long, short = 't')]
     pub alias: Option<String>,
+    /// Broadcast to every participant but ourselves
+    #[clap(long, conflicts_with_all = ["id", "alias"])]
+    pub all: bool,
 }
@@ -1,9 +1,23 @@
 #[cfg(feature = "nostr-sdk")]
 pub mod call;
+#[cfg(feature = "nostr-sdk")]
+pub mod crypto;
+pub mod daemon;
 pub mod db;
 pub mod descriptor;
 #[cfg(feature = "nostr-sdk")]
 pub mod fetch;
+pub mod hook;
+#[cfg(feature = "nostr-sdk")]
+pub mod inbox;
+#[cfg(feature = "nostr-sdk")]
+pub mod key;
+pub mod output;
+pub mod serve;
+#[cfg(feature = "nostr-sdk")]
+pub mod signer;
+#[cfg(feature = "tui")]
+pub mod tui;
 pub mod wallet;
 
 pub use loon::rusqlite;
@@ -1,86 +1,286 @@
+use std::str::FromStr;
+
+use bdk_chain::bitcoin::Psbt;
+use loon::Call;
+use loon::CallPayload;
 use loon::CallTy;
 use loon::Coordinator;
+use loon::Participant;
+use loon::Sent;
 
-use nostr_sdk::{EventBuilder, Kind};
+use nostr_sdk::{EventBuilder, EventId, Kind};
+use serde::Serialize;
 
 use super::bail;
+use super::output;
+use super::Context;
 use super::Result;
 use crate::cli::CallOpt;
 use crate::cli::CallSubCmd;
 use crate::cli::Recipient;
 
 /// Push notes.
-pub async fn push(coordinator: &Coordinator, cmd: CallSubCmd) -> Result<()> {
+pub async fn push(coordinator: &Coordinator, cmd: CallSubCmd, json: bool) -> Result<()> {
     match cmd {
         // Push a plain text note.
         CallSubCmd::Push { note } => {
             let client = coordinator.client();
             client.connect().await;
-            let event = client
+            let output = client
                 .send_event_builder(EventBuilder::new(Kind::TextNote, note))
                 .await?;
-            println!("Sent: {}", event.id());
+            let outcome = Outcome {
+                sent: vec![output.into()],
+                ..Default::default()
+            };
+            display_sent(&outcome, json)?;
         }
         // Push an encrypted payload to a desginated recipient.
         CallSubCmd::New(params) => {
-            let CallOpt {
-                recipient,
-                note,
-                ack,
-                nack,
-                ..
-            } = params;
-
-            // Get the recipient
-            let Recipient { id, alias } = recipient;
-            if let (None, None) = (id, alias.as_ref()) {
-                bail!("no recipient found");
-            }
+            let expires_at = expires_at(&params)?;
+            let calls = new_call(coordinator, &params, expires_at).await?;
 
-            let p = if let Some(id) = id {
-                coordinator
-                    .participants
-                    .get(&id.into())
-                    .ok_or(anyhow::anyhow!("unknown participant id {}", id))?
-            } else {
-                let alias = alias.expect("must have alias");
-                coordinator
-                    .participants()
-                    .find(|(_, p)| p.alias.as_ref() == Some(&alias))
-                    .map(|(_, p)| p)
-                    .ok_or(anyhow::anyhow!("unknown participant {}", alias))?
-            };
-
-            // parse params into a payload
-            let payload = if nack {
-                CallTy::Nack.id().to_string()
-            } else if ack {
-                CallTy::Ack.id().to_string()
-            } else {
-                // text note
-                match note {
-                    Some(s) if !s.trim().is_empty() => {
-                        // nip44 encrypt
-                        coordinator.signer().await?.nip44_encrypt(&p.pk, &s).await?
+            // Send it
+            if params.dryrun {
+                if json {
+                    let calls: Vec<_> = calls.iter().map(|call| call.to_string()).collect();
+                    output::print_json(&serde_json::json!({ "calls": calls }))?;
+                } else {
+                    for call in &calls {
+                        println!("{call}");
                     }
-                    _ => bail!("no message provided"),
                 }
-            };
+            } else {
+                let outcome = send(coordinator, &calls, expires_at).await?;
+                display_sent(&outcome, json)?;
+            }
+        }
+    }
 
-            let call = coordinator.call_new_with_recipient_and_payload(p.quorum_id, &payload);
+    Ok(())
+}
 
-            // Send it
-            if params.dryrun {
-                println!("Preview: {:#?}", &call);
-            } else {
-                let client = coordinator.client();
-                client.connect().await;
-                let event = client
-                    .send_event_builder(EventBuilder::new(Kind::TextNote, call.to_string()))
-                    .await?;
-                println!("Sent: {}", event.id());
+/// Get the participants designated by `recipient`, i.e. every participant but ourselves in
+/// case of a broadcast.
+pub async fn recipients<'a>(
+    coordinator: &'a Coordinator,
+    recipient: &Recipient,
+) -> Result<Vec<&'a Participant>> {
+    let Recipient { id, alias, all } = recipient;
+
+    if *all {
+        let me = coordinator.signer().await?.get_public_key().await?;
+        let ps: Vec<_> = coordinator
+            .participants()
+            .map(|(_, p)| p)
+            .filter(|p| p.pk != me)
+            .collect();
+        if ps.is_empty() {
+            bail!("no other participants");
+        }
+        return Ok(ps);
+    }
+
+    let p = match (id, alias) {
+        (Some(id), _) => coordinator
+            .participants
+            .get(&(*id).into())
+            .ok_or(anyhow::anyhow!("unknown participant id {}", id))?,
+        (None, Some(alias)) => coordinator
+            .participants()
+            .find(|(_, p)| p.alias.as_ref() == Some(alias))
+            .map(|(_, p)| p)
+            .ok_or(anyhow::anyhow!("unknown participant {}", alias))?,
+        (None, None) => bail!("no recipient found"),
+    };
+
+    Ok(vec![p])
+}
+
+/// Unix time at which a call created now with the given `params` expires, if any.
+///
+/// Errors if the ttl is too large.
+pub fn expires_at(params: &CallOpt) -> Result<Option<u64>> {
+    params
+        .ttl
+        .map(|ttl| {
+            super::fetch::now()
+                .checked_add(ttl)
+                .with_context(|| format!("ttl too large: {ttl}"))
+        })
+        .transpose()
+}
+
+/// Create a new call from the given `params` expiring at unix time `expires_at`, returning one
+/// `Call` for each part of the message.
+pub async fn new_call(
+    coordinator: &Coordinator,
+    params: &CallOpt,
+    expires_at: Option<u64>,
+) -> Result<Vec<Call>> {
+    let CallOpt {
+        recipient,
+        note,
+        psbt,
+        signed,
+        memo,
+        ack,
+        nack,
+        reason,
+        ref_event,
+        reply_to,
+        ..
+    } = params;
+
+    let ps = recipients(coordinator, recipient).await?;
+
+    let reply_to = reply_to
+        .as_deref()
+        .map(|id| EventId::parse(id).map(|id| id.to_hex()))
+        .transpose()?;
+    let ref_event = ref_event
+        .as_deref()
+        .map(|id| EventId::parse(id).map(|id| id.to_hex()))
+        .transpose()?
+        .or_else(|| reply_to.clone());
+
+    // parse params into a call type
+    let ty = if *nack {
+        CallTy::Nack {
+            ref_event,
+            reason: reason.clone(),
+        }
+    } else if *ack {
+        CallTy::Ack { ref_event }
+    } else if let Some(psbt) = psbt {
+        let psbt = Psbt::from_str(psbt).context("invalid psbt")?;
+        if *signed {
+            CallTy::PsbtSigned { psbt }
+        } else {
+            CallTy::PsbtRequest {
+                psbt,
+                memo: memo.clone(),
+            }
+        }
+    } else {
+        // text note
+        match note {
+            Some(s) if !s.trim().is_empty() => CallTy::Note { text: s.clone() },
+            _ => bail!("no message provided"),
+        }
+    };
+
+    let signer = coordinator.signer().await?;
+    let payload = CallPayload {
+        expires_at,
+        reply_to,
+        broadcast: recipient.all,
+        ..CallPayload::new(ty)
+    };
+    let recipients: Vec<_> = ps.iter().map(|p| (p.quorum_id, p.pk)).collect();
+    let calls = Call::seal(
+        signer.as_ref(),
+        coordinator.quorum_fingerprint(),
+        &payload,
+        &recipients,
+    )
+    .await?;
+
+    Ok(calls)
+}
+
+/// Outcome of sending the parts of a call.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    /// Events sent, with the outcome at each relay
+    pub sent: Vec<Sent>,
+    /// Parts that couldn't be sent to a recipient
+    pub failed: Vec<Failed>,
+    /// Why the relay lists of the participants couldn't be fetched, if so, in which case the
+    /// parts were only sent to our relays
+    pub hints_error: Option<String>,
+}
+
+/// A part of a call that couldn't be sent.
+#[derive(Debug, Serialize)]
+pub struct Failed {
+    /// Recipient participant id
+    pub pid: u32,
+    pub error: String,
+}
+
+/// Publish the parts of a call to their recipients using the transport of the account,
+/// expiring at unix time `expires_at`, returning the outcome for each recipient.
+///
+/// A part that fails to send doesn't keep the rest from being sent.
+pub async fn send(
+    coordinator: &Coordinator,
+    calls: &[Call],
+    expires_at: Option<u64>,
+) -> Result<Outcome> {
+    let (messenger, hints_error) = coordinator.messenger().await?;
+    let mut ret = Outcome {
+        hints_error: hints_error.map(|e| e.to_string()),
+        ..Default::default()
+    };
+    for call in calls {
+        let call = call.to_string();
+        let pid = Call::parse(&call)?.recipient;
+        let res = match coordinator.participants.get(&pid) {
+            Some(p) => messenger
+                .publish(&p.pk.to_hex(), &call, expires_at)
+                .await
+                .map_err(|e| e.to_string()),
+            None => Err(format!("unknown participant id {pid}")),
+        };
+        match res {
+            Ok(sent) => ret.sent.push(sent),
+            Err(error) => ret.failed.push(Failed {
+                pid: pid.as_u32(),
+                error,
+            }),
+        }
+    }
+
+    Ok(ret)
+}
+
+/// JSON of the `outcome` of a send: the event ids, the outcome at each relay, the parts that
+/// failed and why the relay lists couldn't be fetched, if so.
+pub fn sent_json(outcome: &Outcome) -> serde_json::Value {
+    let ids: Vec<_> = outcome.sent.iter().map(|s| &s.id).collect();
+    serde_json::json!({
+        "event_ids": ids,
+        "relays": outcome.sent,
+        "failed": outcome.failed,
+        "hints_error": outcome.hints_error,
+    })
+}
+
+/// Display the `outcome` of a send, erroring at the end if any part failed.
+fn display_sent(outcome: &Outcome, json: bool) -> Result<()> {
+    if json {
+        output::print_json(&sent_json(outcome))?;
+    } else {
+        if let Some(e) = &outcome.hints_error {
+            eprintln!("Failed to fetch relay lists, sent to our relays only: {e}");
+        }
+        for s in &outcome.sent {
+            println!("Sent: {}", s.id);
+            for (relay, e) in &s.failed {
+                eprintln!("  {relay} failed: {e}");
             }
         }
+        for failed in &outcome.failed {
+            eprintln!("Failed to send to participant {}: {}", failed.pid, failed.error);
+        }
+    }
+    if !outcome.failed.is_empty() {
+        bail!(
+            "failed to send {} of {} events",
+            outcome.failed.len(),
+            outcome.failed.len() + outcome.sent.len()
+        );
     }
 
     Ok(())
@@ -0,0 +1,76 @@
+use std::io::Read;
+
+use loon::{Call, Chunk, Coordinator};
+use nostr_sdk::PublicKey;
+
+use super::output;
+use super::{bail, Context, Result};
+use crate::cli::CryptoSubCmd;
+
+/// Execute nip44 operation.
+pub async fn execute(coordinator: &Coordinator, subcmd: CryptoSubCmd, json: bool) -> Result<()> {
+    let signer = coordinator.signer().await?;
+
+    match subcmd {
+        CryptoSubCmd::Encrypt { to, message } => {
+            let pk = public_key(coordinator, &to)?;
+            let ciphertext = signer.nip44_encrypt(&pk, &message_or_stdin(message)?).await?;
+            if json {
+                output::print_json(&serde_json::json!({ "ciphertext": ciphertext }))?;
+            } else {
+                println!("{ciphertext}");
+            }
+        }
+        CryptoSubCmd::Decrypt { from, message } => {
+            let pk = public_key(coordinator, &from)?;
+            let message = message_or_stdin(message)?;
+            let message = message.trim();
+
+            // a call carries the ciphertext after its header
+            let ciphertext = if message.starts_with(loon::HRP) {
+                Call::parse(message)?.payload
+            } else {
+                message.to_string()
+            };
+            let mut plaintext = signer.nip44_decrypt(&pk, &ciphertext).await?;
+            // unwrap a message sent in a single chunk
+            if let Ok(chunk) = serde_json::from_str::<Chunk>(&plaintext) {
+                if chunk.total == 1 {
+                    plaintext = chunk.data;
+                }
+            }
+            if json {
+                output::print_json(&serde_json::json!({ "plaintext": plaintext }))?;
+            } else {
+                println!("{plaintext}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the public key of `s`, either an npub, a hex public key or the alias of a participant.
+fn public_key(coordinator: &Coordinator, s: &str) -> Result<PublicKey> {
+    if let Ok(pk) = PublicKey::parse(s) {
+        return Ok(pk);
+    }
+    match coordinator.participants().find(|(_, p)| p.alias.as_deref() == Some(s)) {
+        Some((_, p)) => Ok(p.pk),
+        None => bail!("unknown participant {s}"),
+    }
+}
+
+/// Get the `message`, or read it from stdin if not given, less the trailing newline.
+fn message_or_stdin(message: Option<String>) -> Result<String> {
+    match message {
+        Some(message) => Ok(message),
+        None => {
+            let mut buf = String::new();
+            std::io::stdin()
+                .read_to_string(&mut buf)
+                .context("failed to read stdin")?;
+            Ok(buf.strip_suffix('\n').unwrap_or(&buf).to_string())
+        }
+    }
+}
@@ -0,0 +1,430 @@
+#[cfg(feature = "nostr-sdk")]
+use std::cell::Cell;
+#[cfg(feature = "nostr-sdk")]
+use std::collections::HashSet;
+use std::fmt::Write;
+#[cfg(feature = "nostr-sdk")]
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bdk_chain::bitcoin::BlockHash;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::{self, LocalSet};
+use tokio::time;
+
+use loon::{Coordinator, SyncProgress, WalletEvent};
+
+use super::bail;
+use super::output::BalanceInfo;
+use super::Result;
+use crate::cli::{Cmd, DaemonOpt, WalletSubCmd};
+
+/// How often to poll the chain source and the nostr relay.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to wait on a client of the daemon socket.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Capacity of the channel used to wake the daemon between polls.
+const WAKE_CAPACITY: usize = 100;
+
+/// Start of the response to a request the daemon can't serve, whatever the output format.
+const ERROR_PREFIX: &str = "error: ";
+
+/// Reasons to wake the daemon between polls.
+#[derive(Debug)]
+enum Wake {
+    /// Bitcoind ZMQ notification
+    #[cfg(feature = "zmq")]
+    Notification(loon::Notification),
+}
+
+/// Requests that can be served by a running daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// Daemon status
+    Status,
+    /// Wallet balance
+    Balance,
+}
+
+impl Request {
+    /// Get the request that is able to serve `cmd`, if any.
+    pub fn from_cmd(cmd: &Cmd) -> Option<Self> {
+        match cmd {
+            Cmd::Status => Some(Self::Status),
+            Cmd::Wallet(WalletSubCmd::Balance) => Some(Self::Balance),
+            _ => None,
+        }
+    }
+
+    /// Parse a request from the line sent by a client.
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "status" => Some(Self::Status),
+            "balance" => Some(Self::Balance),
+            _ => None,
+        }
+    }
+}
+
+impl AsRef<str> for Request {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Status => "status",
+            Self::Balance => "balance",
+        }
+    }
+}
+
+/// Work for the chain thread of the daemon.
+#[derive(Debug)]
+enum Job {
+    /// Sync if the chain source has a new best block
+    Sync,
+    /// Handle a wake of the daemon
+    Wake(Wake),
+}
+
+/// Outcome of a [`Job`].
+#[derive(Debug)]
+struct Done {
+    chain: ChainStatus,
+    events: Result<Vec<WalletEvent>>,
+}
+
+/// State of the chain as of the last sync.
+#[derive(Debug, Default, Clone, Copy)]
+struct ChainStatus {
+    /// Best block hash of the chain source as of the last sync
+    best_block: Option<BlockHash>,
+    /// Unix time of the last sync
+    last_sync: Option<u64>,
+}
+
+/// State of a running daemon, as seen by a request.
+#[derive(Debug, Default, Clone)]
+struct Status {
+    chain: ChainStatus,
+    /// Count of notes received, shared with the note poll
+    #[cfg(feature = "nostr-sdk")]
+    notes: Rc<Cell<usize>>,
+}
+
+/// Path to the daemon socket of the account with the given id.
+pub fn socket_path(account_id: u32) -> String {
+    format!("loon-{account_id}.sock")
+}
+
+/// Send a `request` to the daemon of the given account, returning the response.
+///
+/// Returns `None` if no daemon is listening for the account. Errors if the daemon can't serve
+/// the request, e.g. while it is busy.
+pub async fn query(account_id: u32, request: Request, json: bool) -> Result<Option<String>> {
+    let mut stream = match UnixStream::connect(socket_path(account_id)).await {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None),
+    };
+    let format = if json { " json" } else { "" };
+    stream
+        .write_all(format!("{}{}\n", request.as_ref(), format).as_bytes())
+        .await?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    if let Some(e) = response.strip_prefix(ERROR_PREFIX) {
+        bail!("daemon failed: {}", e.trim_end());
+    }
+
+    Ok(Some(response))
+}
+
+/// Keeps the coordinator alive, syncing the wallet on each new block and polling for notes,
+/// while serving requests on the account's socket. Runs until interrupted.
+///
+/// New blocks are found by polling the chain source, unless ZMQ endpoints are configured.
+/// Syncing blocks, so it's done on a thread of its own. Note polls, requests and hooks run as
+/// tasks of their own, and requests for the wallet are turned away during a sync or a poll.
+/// Stops with an error if the chain thread does.
+pub async fn run(
+    coordinator: Coordinator,
+    account_id: u32,
+    #[cfg_attr(not(feature = "zmq"), allow(unused_variables))] opt: DaemonOpt,
+) -> Result<()> {
+    let path = socket_path(account_id);
+    if UnixStream::connect(&path).await.is_ok() {
+        bail!("daemon already running for account id {account_id}");
+    }
+    // Remove the socket of a daemon that did not exit cleanly.
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    println!("Listening on {path}");
+
+    // The sender is held for the life of the daemon so that the channel stays open.
+    let (wake_tx, mut wake_rx) = mpsc::channel::<Wake>(WAKE_CAPACITY);
+    #[cfg_attr(not(feature = "zmq"), allow(unused_mut))]
+    let mut poll_chain = true;
+
+    #[cfg(feature = "zmq")]
+    if !opt.zmq.is_empty() {
+        let subscriber = loon::ZmqSubscriber::connect(opt.zmq.iter().map(String::as_str))?;
+        let mut notifications = subscriber.stream();
+        let wake_tx = wake_tx.clone();
+        tokio::spawn(async move {
+            while let Some(res) = notifications.recv().await {
+                match res {
+                    Ok(notification) => {
+                        if wake_tx.send(Wake::Notification(notification)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("ZMQ subscriber failed: {e}");
+                        break;
+                    }
+                }
+            }
+        });
+        poll_chain = false;
+    }
+
+    let coordinator = Arc::new(Mutex::new(coordinator));
+    let (job_tx, job_rx) = std::sync::mpsc::channel::<Job>();
+    let (done_tx, mut done_rx) = mpsc::unbounded_channel::<Done>();
+    {
+        let coordinator = coordinator.clone();
+        std::thread::spawn(move || run_jobs(&coordinator, job_rx, done_tx));
+    }
+
+    let mut status = Status::default();
+    let mut interval = time::interval(POLL_INTERVAL);
+    // count of jobs sent to the chain thread and not yet done
+    let mut pending = 0;
+    let shutdown = tokio::signal::ctrl_c();
+    tokio::pin!(shutdown);
+
+    // the note poll holds the db across awaits, so tasks are local to this thread
+    let tasks = LocalSet::new();
+    let res = tasks
+        .run_until(async {
+            #[cfg(feature = "nostr-sdk")]
+            task::spawn_local(poll_notes(coordinator.clone(), status.notes.clone()));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        // Always sync on startup, after which we may rely on notifications.
+                        if (poll_chain || status.chain.best_block.is_none()) && pending == 0 {
+                            job_tx.send(Job::Sync)?;
+                            pending += 1;
+                        }
+                    }
+                    res = listener.accept() => {
+                        let (stream, _) = res?;
+                        let (coordinator, status) = (coordinator.clone(), status.clone());
+                        task::spawn_local(async move {
+                            let serve = serve(&coordinator, &status, stream);
+                            match time::timeout(CLIENT_TIMEOUT, serve).await {
+                                Ok(Ok(())) => {}
+                                Ok(Err(e)) => eprintln!("Request failed: {e}"),
+                                Err(_) => eprintln!("Request timed out"),
+                            }
+                        });
+                    }
+                    Some(wake) = wake_rx.recv() => {
+                        job_tx.send(Job::Wake(wake))?;
+                        pending += 1;
+                    }
+                    done = done_rx.recv() => {
+                        let Some(done) = done else {
+                            bail!("chain thread stopped, no longer syncing");
+                        };
+                        pending -= 1;
+                        status.chain = done.chain;
+                        match done.events {
+                            Ok(events) => {
+                                task::spawn_local(async move {
+                                    super::hook::on_wallet_events(account_id, &events).await;
+                                });
+                            }
+                            Err(e) => eprintln!("Sync failed: {e}"),
+                        }
+                    }
+                    res = &mut shutdown => {
+                        res?;
+                        break;
+                    }
+                }
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await;
+
+    // a sync in progress is abandoned, its changes aren't persisted, as are a note poll and
+    // hooks still running
+    drop(job_tx);
+    drop(wake_tx);
+    std::fs::remove_file(&path)?;
+
+    res
+}
+
+/// Run the chain `jobs` sent by the daemon on the current thread, sending back the outcome of
+/// each, until the daemon stops.
+fn run_jobs(
+    coordinator: &Mutex<Coordinator>,
+    jobs: std::sync::mpsc::Receiver<Job>,
+    done: mpsc::UnboundedSender<Done>,
+) {
+    let mut chain = ChainStatus::default();
+    for job in jobs {
+        let mut coordinator = coordinator.blocking_lock();
+        let events = match job {
+            Job::Sync => sync(&mut coordinator, &mut chain),
+            Job::Wake(wake) => handle_wake(&mut coordinator, &mut chain, wake),
+        };
+        drop(coordinator);
+        if done.send(Done { chain, events }).is_err() {
+            break;
+        }
+    }
+}
+
+/// Sync the wallet if the chain source has a new best block, returning the wallet events.
+fn sync(coordinator: &mut Coordinator, chain: &mut ChainStatus) -> Result<Vec<WalletEvent>> {
+    let best_block = coordinator.rpc_client().get_best_block_hash()?;
+    if chain.best_block == Some(best_block) {
+        return Ok(vec![]);
+    }
+
+    let events = coordinator.sync(None, |progress| {
+        if let SyncProgress::Matched(height) = progress {
+            println!("Matched block {height}");
+        }
+    })?;
+    coordinator.persist()?;
+    for event in &events {
+        println!("{event}");
+    }
+
+    chain.best_block = Some(best_block);
+    chain.last_sync = Some(std::time::UNIX_EPOCH.elapsed()?.as_secs());
+    println!("Local tip: {}", coordinator.wallet().tip().height());
+
+    Ok(events)
+}
+
+/// Handle a `wake` of the daemon, returning the wallet events.
+#[cfg_attr(not(feature = "zmq"), allow(unused_variables))]
+fn handle_wake(
+    coordinator: &mut Coordinator,
+    chain: &mut ChainStatus,
+    wake: Wake,
+) -> Result<Vec<WalletEvent>> {
+    match wake {
+        #[cfg(feature = "zmq")]
+        Wake::Notification(loon::Notification::Block(_)) => sync(coordinator, chain),
+        #[cfg(feature = "zmq")]
+        Wake::Notification(notification) => {
+            let events = coordinator.handle_notification(notification)?;
+            for event in &events {
+                println!("{event}");
+            }
+            coordinator.persist()?;
+            Ok(events)
+        }
+    }
+}
+
+/// Fetch new notes from quorum participants into the inbox on each poll interval, counting
+/// the `notes` received. Skips a poll while the chain thread is busy.
+#[cfg(feature = "nostr-sdk")]
+async fn poll_notes(coordinator: Arc<Mutex<Coordinator>>, notes: Rc<Cell<usize>>) {
+    // ids of the events seen
+    let mut seen = HashSet::new();
+    let mut interval = time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Ok(coordinator) = coordinator.try_lock() else {
+            continue;
+        };
+        match super::fetch::poll(&coordinator, &mut seen, false).await {
+            Ok(polled) if !polled.entries.is_empty() => {
+                notes.set(notes.get() + polled.entries.len());
+                println!("Received {} notes", polled.entries.len());
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Fetch failed: {e}"),
+        }
+    }
+}
+
+/// Respond to a single request read from `stream`.
+async fn serve(
+    coordinator: &Mutex<Coordinator>,
+    status: &Status,
+    stream: UnixStream,
+) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+
+    // A request is followed by an optional output format.
+    let mut words = line.split_whitespace();
+    let request = words.next().and_then(Request::parse);
+    let json = words.next() == Some("json");
+
+    // rather than wait for a sync or a note poll
+    let Ok(coordinator) = coordinator.try_lock() else {
+        let response = format!("{ERROR_PREFIX}busy, try again later\n");
+        stream.get_mut().write_all(response.as_bytes()).await?;
+        return Ok(());
+    };
+    let coordinator = &*coordinator;
+
+    let response = match request {
+        Some(Request::Status) if json => status_json(coordinator, status)?,
+        Some(Request::Status) => format_status(coordinator, status)?,
+        Some(Request::Balance) if json => {
+            serde_json::to_string_pretty(&BalanceInfo::new(coordinator.wallet())?)? + "\n"
+        }
+        Some(Request::Balance) => super::wallet::format_balance(coordinator)?,
+        None => format!("{ERROR_PREFIX}unknown request: {}\n", line.trim()),
+    };
+    stream.get_mut().write_all(response.as_bytes()).await?;
+
+    Ok(())
+}
+
+/// Format the daemon status as JSON.
+fn status_json(coordinator: &Coordinator, status: &Status) -> Result<String> {
+    let tip = coordinator.wallet().tip();
+    #[allow(unused_mut)]
+    let mut value = serde_json::json!({
+        "height": tip.height(),
+        "hash": tip.hash().to_string(),
+        "last_sync": status.chain.last_sync,
+    });
+    #[cfg(feature = "nostr-sdk")]
+    {
+        value["notes"] = status.notes.get().into();
+    }
+
+    Ok(serde_json::to_string_pretty(&value)? + "\n")
+}
+
+/// Format the daemon status for display.
+fn format_status(coordinator: &Coordinator, status: &Status) -> Result<String> {
+    let mut s = String::new();
+    let tip = coordinator.wallet().tip();
+    writeln!(s, "Local tip: {} {}", tip.height(), tip.hash())?;
+    if let Some(time) = status.chain.last_sync {
+        writeln!(s, "Last sync: {time}")?;
+    }
+    #[cfg(feature = "nostr-sdk")]
+    writeln!(s, "Notes received: {}", status.notes.get())?;
+
+    Ok(s)
+}
@@ -1,12 +1,20 @@
+use loon::Account;
+use loon::Friend;
+use loon::Transport;
+
+use super::output;
 use super::rusqlite;
 use super::rusqlite::named_params;
 use crate::cli::Cmd;
 use crate::cli::DbSubCmd;
 
+/// Schema of the loon db.
+const SCHEMA: &str = include_str!("../../schema.sql");
+
 /// Execute database operation.
-pub fn execute(cmd: &Cmd) -> anyhow::Result<()> {
+pub fn execute(cmd: &Cmd, json: bool) -> anyhow::Result<()> {
     if let Cmd::Db(cmd) = cmd {
-        let db = rusqlite::Connection::open(loon::LOON_DB_PATH)?;
+        let db = open()?;
 
         match cmd {
             // Insert into account
@@ -14,19 +22,16 @@ pub fn execute(cmd: &Cmd) -> anyhow::Result<()> {
                 network,
                 nick,
                 descriptor,
+                transport,
             } => {
-                let mut stmt = db.prepare(
-                    "INSERT INTO account (network, nick, descriptor) VALUES (:network, :nick, :descriptor)",
-                )?;
-                let ct = stmt.execute(
-                    named_params! {":network": network, ":nick": nick, ":descriptor": descriptor},
-                )?;
-                println!("Inserted {ct} rows into table account");
-
-                // get current acct id
-                let mut stmt = db.prepare("SELECT max(id) FROM account")?;
-                let id = stmt.query_row([], |row| row.get::<usize, usize>(0))?;
-                println!("Row id {id}");
+                let id = insert_account(&db, network, nick, descriptor, *transport)?;
+
+                if json {
+                    output::print_json(&serde_json::json!({ "inserted": 1, "id": id }))?;
+                } else {
+                    println!("Inserted 1 rows into table account");
+                    println!("Row id {id}");
+                }
             }
             // Insert into friend
             DbSubCmd::Friend {
@@ -35,13 +40,319 @@ pub fn execute(cmd: &Cmd) -> anyhow::Result<()> {
                 npub,
                 alias,
             } => {
-                let mut stmt = db.prepare("INSERT INTO friend (account_id, quorum_id, npub, alias) VALUES (:account_id, :quorum_id, :npub, :alias)")?;
-                let ct =
-                    stmt.execute(named_params! {":account_id": account_id, ":quorum_id": quorum_id, ":npub": npub, ":alias": alias})?;
-                println!("Inserted {ct} rows into table friend");
+                let ct = insert_friend(&db, *account_id, *quorum_id, npub, Some(alias.as_str()))?;
+                if json {
+                    output::print_json(&serde_json::json!({ "inserted": ct }))?;
+                } else {
+                    println!("Inserted {ct} rows into table friend");
+                }
+            }
+            // Insert into, delete from or list hook
+            DbSubCmd::Hook {
+                account_id,
+                event,
+                command,
+                url,
+                remove,
+            } => match (event, remove) {
+                (_, Some(id)) => {
+                    let ct = db.execute(
+                        "DELETE FROM hook WHERE account_id = ?1 AND id = ?2",
+                        rusqlite::params![account_id, id],
+                    )?;
+                    if json {
+                        output::print_json(&serde_json::json!({ "deleted": ct }))?;
+                    } else {
+                        println!("Deleted {ct} rows from table hook");
+                    }
+                }
+                (Some(event), None) => {
+                    let id = super::hook::insert(
+                        &db,
+                        *account_id,
+                        *event,
+                        command.as_deref(),
+                        url.as_deref(),
+                    )?;
+                    if json {
+                        output::print_json(&serde_json::json!({ "inserted": 1, "id": id }))?;
+                    } else {
+                        println!("Inserted 1 rows into table hook");
+                        println!("Row id {id}");
+                    }
+                }
+                (None, None) => {
+                    let hooks = super::hook::list(&db, *account_id)?;
+                    if json {
+                        let hooks: Vec<_> = hooks
+                            .iter()
+                            .map(|hook| {
+                                serde_json::json!({
+                                    "id": hook.id,
+                                    "event": hook.event,
+                                    "command": hook.command,
+                                    "url": hook.url,
+                                })
+                            })
+                            .collect();
+                        output::print_json(&hooks)?;
+                    } else {
+                        for hook in hooks {
+                            let action = hook.command.or(hook.url).unwrap_or_default();
+                            println!("{}: on {} {action}", hook.id, hook.event);
+                        }
+                    }
+                }
+            },
+            // Insert into, delete from or list relay
+            DbSubCmd::Relay {
+                account_id,
+                url,
+                remove,
+            } => match url {
+                Some(url) if *remove => {
+                    let ct = db.execute(
+                        "DELETE FROM relay WHERE account_id = ?1 AND url = ?2",
+                        rusqlite::params![account_id, url],
+                    )?;
+                    if json {
+                        output::print_json(&serde_json::json!({ "deleted": ct }))?;
+                    } else {
+                        println!("Deleted {ct} rows from table relay");
+                    }
+                }
+                Some(url) => {
+                    let ct = insert_relay(&db, *account_id, url)?;
+                    if json {
+                        output::print_json(&serde_json::json!({ "inserted": ct }))?;
+                    } else {
+                        println!("Inserted {ct} rows into table relay");
+                    }
+                }
+                None => {
+                    let relays = list_relays(&db, *account_id)?;
+                    if json {
+                        output::print_json(&relays)?;
+                    } else {
+                        for url in relays {
+                            println!("{url}");
+                        }
+                    }
+                }
+            },
+            // Update account transport
+            DbSubCmd::Transport {
+                account_id,
+                transport,
+            } => {
+                let ct = set_transport(&db, *account_id, *transport)?;
+                if json {
+                    output::print_json(&serde_json::json!({ "updated": ct }))?;
+                } else {
+                    println!("Updated {ct} rows in table account");
+                }
             }
         }
     }
 
     Ok(())
 }
+
+/// Open the loon db, migrating it to the current schema.
+pub fn open() -> anyhow::Result<rusqlite::Connection> {
+    let db = rusqlite::Connection::open(loon::LOON_DB_PATH)?;
+    migrate(&db)?;
+
+    Ok(db)
+}
+
+/// Create the tables missing from the db, and add the columns missing from a db created by an
+/// earlier version of the schema.
+pub fn migrate(db: &rusqlite::Connection) -> anyhow::Result<()> {
+    db.execute_batch(SCHEMA)?;
+
+    // account.transport
+    let columns = self::columns(db, "account")?;
+    if !columns.iter().any(|name| name == "transport") {
+        db.execute(
+            "ALTER TABLE account ADD COLUMN transport TEXT NOT NULL DEFAULT 'note'",
+            [],
+        )?;
+    }
+    let columns = self::columns(db, "inbox")?;
+    // inbox.expires_at, inbox.archived
+    if !columns.iter().any(|name| name == "archived") {
+        db.execute("ALTER TABLE inbox ADD COLUMN expires_at INTEGER", [])?;
+        db.execute("ALTER TABLE inbox ADD COLUMN archived INTEGER NOT NULL DEFAULT 0", [])?;
+    }
+    // inbox.reply_to
+    if !columns.iter().any(|name| name == "reply_to") {
+        db.execute("ALTER TABLE inbox ADD COLUMN reply_to TEXT", [])?;
+    }
+    // inbox.broadcast
+    if !columns.iter().any(|name| name == "broadcast") {
+        db.execute("ALTER TABLE inbox ADD COLUMN broadcast INTEGER NOT NULL DEFAULT 0", [])?;
+    }
+    // inbox.response
+    if !columns.iter().any(|name| name == "response") {
+        db.execute("ALTER TABLE inbox ADD COLUMN response TEXT", [])?;
+    }
+    // inbox keyed by account and event id, rather than by event id only
+    if self::primary_key(db, "inbox")? == ["event_id"] {
+        let tx = db.unchecked_transaction()?;
+        tx.execute("ALTER TABLE inbox RENAME TO inbox_old", [])?;
+        tx.execute_batch(SCHEMA)?;
+        tx.execute(
+            "INSERT INTO inbox (event_id, account_id, pid, created_at, ty, payload, read, expires_at, archived, reply_to, broadcast, response) SELECT event_id, account_id, pid, created_at, ty, payload, read, expires_at, archived, reply_to, broadcast, response FROM inbox_old",
+            [],
+        )?;
+        tx.execute("DROP TABLE inbox_old", [])?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Names of the columns of `table`, empty if there is no such table.
+fn columns(db: &rusqlite::Connection, table: &str) -> anyhow::Result<Vec<String>> {
+    let mut stmt = db.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let columns = stmt
+        .query_map([table], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(columns)
+}
+
+/// Names of the columns of the primary key of `table`, in order.
+fn primary_key(db: &rusqlite::Connection, table: &str) -> anyhow::Result<Vec<String>> {
+    let mut stmt = db.prepare("SELECT name FROM pragma_table_info(?1) WHERE pk > 0 ORDER BY pk")?;
+    let columns = stmt
+        .query_map([table], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(columns)
+}
+
+/// Insert a new quorum account, returning the account id.
+pub fn insert_account(
+    db: &rusqlite::Connection,
+    network: &str,
+    nick: &str,
+    descriptor: &str,
+    transport: Transport,
+) -> anyhow::Result<usize> {
+    let mut stmt = db.prepare(
+        "INSERT INTO account (network, nick, descriptor, transport) VALUES (:network, :nick, :descriptor, :transport)",
+    )?;
+    stmt.execute(named_params! {":network": network, ":nick": nick, ":descriptor": descriptor, ":transport": transport.to_string()})?;
+
+    // get current acct id
+    let mut stmt = db.prepare("SELECT max(id) FROM account")?;
+    let id = stmt.query_row([], |row| row.get::<usize, usize>(0))?;
+
+    Ok(id)
+}
+
+/// Insert a new participant to an existing quorum, returning the count of rows inserted.
+///
+/// Errors if `quorum_id` can't be addressed by a call, see [`loon::Pid::MAX`].
+pub fn insert_friend(
+    db: &rusqlite::Connection,
+    account_id: u32,
+    quorum_id: u32,
+    npub: &str,
+    alias: Option<&str>,
+) -> anyhow::Result<usize> {
+    if quorum_id > loon::Pid::MAX {
+        anyhow::bail!("quorum id {quorum_id} exceeds the maximum {}", loon::Pid::MAX);
+    }
+    let mut stmt = db.prepare("INSERT INTO friend (account_id, quorum_id, npub, alias) VALUES (:account_id, :quorum_id, :npub, :alias)")?;
+    let ct = stmt.execute(named_params! {":account_id": account_id, ":quorum_id": quorum_id, ":npub": npub, ":alias": alias})?;
+
+    Ok(ct)
+}
+
+/// Set the call transport of an account, returning the count of rows updated.
+pub fn set_transport(
+    db: &rusqlite::Connection,
+    account_id: u32,
+    transport: Transport,
+) -> anyhow::Result<usize> {
+    let ct = db.execute(
+        "UPDATE account SET transport = ?1 WHERE id = ?2",
+        rusqlite::params![transport.to_string(), account_id],
+    )?;
+
+    Ok(ct)
+}
+
+/// Add a relay to the account, returning the count of rows inserted.
+///
+/// Errors if `url` isn't a websocket url.
+pub fn insert_relay(
+    db: &rusqlite::Connection,
+    account_id: u32,
+    url: &str,
+) -> anyhow::Result<usize> {
+    if !url.starts_with("wss://") && !url.starts_with("ws://") {
+        anyhow::bail!("relay url must start with wss:// or ws://");
+    }
+    let ct = db.execute(
+        "INSERT OR IGNORE INTO relay (account_id, url) VALUES (?1, ?2)",
+        rusqlite::params![account_id, url],
+    )?;
+
+    Ok(ct)
+}
+
+/// List the relays of the account.
+pub fn list_relays(db: &rusqlite::Connection, account_id: u32) -> anyhow::Result<Vec<String>> {
+    let mut stmt = db.prepare("SELECT url FROM relay WHERE account_id = ?1 ORDER BY url")?;
+    let relays = stmt
+        .query_map([account_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(relays)
+}
+
+/// List the friends of the account, i.e. the participants of its quorum.
+pub fn list_friends(db: &rusqlite::Connection, account_id: u32) -> anyhow::Result<Vec<Friend>> {
+    let mut stmt = db.prepare(
+        "SELECT account_id, quorum_id, npub, alias FROM friend WHERE account_id = ?1 ORDER BY quorum_id",
+    )?;
+    let friends = stmt
+        .query_map([account_id], |row| {
+            Ok(Friend {
+                account_id: row.get(0)?,
+                quorum_id: row.get(1)?,
+                npub: row.get(2)?,
+                alias: row.get(3)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(friends)
+}
+
+/// Get the account with the given `id`, if it exists.
+pub fn get_account(db: &rusqlite::Connection, id: u32) -> anyhow::Result<Option<Account>> {
+    Ok(list_accounts(db)?.into_iter().find(|account| account.id == id))
+}
+
+/// List all accounts.
+pub fn list_accounts(db: &rusqlite::Connection) -> anyhow::Result<Vec<Account>> {
+    let mut stmt = db.prepare("SELECT id, network, nick, descriptor, transport FROM account")?;
+    let accounts = stmt
+        .query_map([], |row| {
+            Ok(Account {
+                id: row.get(0)?,
+                network: row.get(1)?,
+                nick: row.get(2)?,
+                descriptor: row.get(3)?,
+                transport: row.get(4)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(accounts)
+}
@@ -1,10 +1,12 @@
+use bdk_chain::{bitcoin::secp256k1, miniscript::Descriptor, DescriptorExt};
 use loon::simplerpc::types::ImportDescriptorsRequest;
 use loon::Coordinator;
 
+use super::output;
 use crate::cli::DescSubCmd;
 
 // Descriptor utilities.
-pub fn execute(coordinator: &Coordinator, subcmd: DescSubCmd) -> super::Result<()> {
+pub fn execute(coordinator: &Coordinator, subcmd: DescSubCmd, json: bool) -> super::Result<()> {
     let client = coordinator.rpc_client();
 
     match subcmd {
@@ -23,14 +25,35 @@ pub fn execute(coordinator: &Coordinator, subcmd: DescSubCmd) -> super::Result<(
             };
 
             let res = client.import_descriptors(&[request])?;
-            println!("{res:#?}");
+            if json {
+                output::print_json(&res)?;
+            } else {
+                println!("{res:#?}");
+            }
         }
         // Get descriptor info.
         DescSubCmd::Info { desc } => {
             let res = client.get_descriptor_info(&desc)?;
-            println!("{res:#?}");
+            if json {
+                output::print_json(&res)?;
+            } else {
+                println!("{res:#?}");
+            }
         }
     }
 
     Ok(())
 }
+
+/// Quorum fingerprint of an account `descriptor`, i.e. the first 8 hex characters of the
+/// descriptor id of its first single descriptor.
+pub fn fingerprint(descriptor: &str) -> super::Result<String> {
+    let secp = secp256k1::Secp256k1::new();
+    let desc = Descriptor::parse_descriptor(&secp, descriptor)?.0;
+    let Some(desc) = desc.into_single_descriptors()?.into_iter().next() else {
+        super::bail!("empty descriptor");
+    };
+    let did = desc.descriptor_id().to_string();
+
+    Ok(did[..8].to_string())
+}
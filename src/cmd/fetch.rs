@@ -1,151 +1,613 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::time::Duration;
-use tokio::fs;
-use tokio::io::AsyncWriteExt;
 use tokio::time;
 
+use loon::Account;
+use loon::Call;
+use loon::CallError;
+use loon::CallPayload;
 use loon::CallTy;
 use loon::ChatEntry;
 use loon::Coordinator;
-use nostr_sdk::{EventId, Filter, Kind, PublicKey, Timestamp};
+use loon::Envelope;
+use loon::Messenger;
+use loon::NostrMessenger;
+use loon::Opened;
+use nostr_sdk::{PublicKey, Timestamp};
 
-use super::Result;
+use super::inbox;
+use super::output::{self, ChatInfo, OtherCalls};
+use super::rusqlite;
+use super::{bail, Result};
 
-/// How far to look back in seconds when polling the relay, currently one fortnight.
+/// How far to look back in seconds when first fetching from a relay, currently one fortnight.
 const DEFAULT_LOOKBACK: u64 = 14 * 24 * 60 * 60;
 
-/// App default nostr client timeout.
-const TIMEOUT: Duration = Duration::from_secs(10);
+/// New chat entries from a [`poll`], with the calls seen for the quorums of other accounts.
+pub struct Polled {
+    pub entries: Vec<ChatEntry>,
+    pub others: Vec<OtherCalls>,
+}
+
+/// Chat entries from [`decrypt_envelopes`].
+struct Decrypted {
+    /// Calls for us
+    calls: Vec<ChatEntry>,
+    /// Plain notes that aren't calls
+    notes: Vec<ChatEntry>,
+}
 
-/// Encrypted content and author keyed by `EventId`.
-type RawEntries = HashMap<EventId, (PublicKey, String)>;
+/// Another account, with the count of calls seen for its quorum.
+struct OtherAccount {
+    account: Account,
+    /// Participants of the quorum, as hex encoded public keys
+    peers: HashSet<String>,
+    calls: usize,
+}
 
 /// Fetch latest notes by quorum parties, printing results to stdout.
-pub async fn fetch_and_decrypt(coordinator: &Coordinator) -> Result<()> {
-    let raw_entries = fetch_raw_entries(coordinator).await?;
-    let entries = decrypt_raw_entries(coordinator, raw_entries.values().cloned()).await?;
-    for entry in entries {
-        println!("{}: {}", entry.alias, entry.message);
+pub async fn fetch_and_decrypt(
+    coordinator: &Coordinator,
+    calls_only: bool,
+    json: bool,
+) -> Result<()> {
+    let Polled { entries, others } = poll(coordinator, &mut HashSet::new(), calls_only).await?;
+    if json {
+        let calls: Vec<_> = entries.iter().map(ChatInfo::from).collect();
+        return output::print_json(
+            &serde_json::json!({ "calls": calls, "other_accounts": others }),
+        );
     }
+    print_threads(coordinator, &entries)
+}
+
+/// Print chat `entries` as threads, each call followed by the replies to it, indented.
+///
+/// Replies to a call not among `entries` are grouped under it, showing the call if it is
+/// found in the inbox, else its event id.
+fn print_threads(coordinator: &Coordinator, entries: &[ChatEntry]) -> Result<()> {
+    let mut entries = entries.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.created_at);
+    let ids = entries
+        .iter()
+        .map(|entry| entry.event_id.as_str())
+        .collect::<HashSet<_>>();
+
+    // parents of replies, in order of the first reply
+    let mut parents = vec![];
+    for entry in &entries {
+        match entry.reply_to.as_deref() {
+            Some(id) if ids.contains(id) => {}
+            parent => {
+                if !parents.contains(&parent) {
+                    parents.push(parent);
+                }
+            }
+        }
+    }
+
+    let db = super::db::open()?;
+    let inbox = inbox::list(&db, coordinator.account_id)?;
+    for parent in parents {
+        let depth = match parent {
+            // top level calls
+            None => 0,
+            Some(id) => {
+                match inbox.iter().find(|entry| entry.event_id == id) {
+                    Some(entry) => {
+                        let chat = inbox::chat_entry(coordinator, entry)?;
+                        println!("{}: {}", chat.alias, chat.call);
+                    }
+                    None => println!("Re {id}"),
+                }
+                1
+            }
+        };
+        for entry in entries.iter().filter(|entry| entry.reply_to.as_deref() == parent) {
+            print_thread(entry, &entries, depth);
+        }
+    }
+
     Ok(())
 }
 
-/// Fetch events from quorum participants.
-async fn fetch_raw_entries(coordinator: &Coordinator) -> Result<RawEntries> {
-    let client = coordinator.client();
-    client.connect().await;
-    let mut entries = RawEntries::new();
+/// Print `entry` indented to `depth`, followed by the replies to it found in `entries`.
+fn print_thread(entry: &ChatEntry, entries: &[&ChatEntry], depth: usize) {
+    let to = if entry.broadcast { " (to all)" } else { "" };
+    println!("{}{}{to}: {}", "  ".repeat(depth), entry.alias, entry.call);
+    for reply in entries
+        .iter()
+        .filter(|reply| reply.reply_to.as_deref() == Some(entry.event_id.as_str()))
+    {
+        print_thread(reply, entries, depth + 1);
+    }
+}
 
-    let subs = Filter::new()
-        .pubkeys(coordinator.participants().map(|(_, p)| p.pk))
-        .since((Timestamp::now().as_secs() - DEFAULT_LOOKBACK).into());
+/// Fetch latest notes by quorum parties not already in the inbox, and add them to the inbox.
+/// Plain notes that aren't calls are left out if `calls_only`.
+pub async fn fetch(coordinator: &Coordinator, calls_only: bool) -> Result<Vec<ChatEntry>> {
+    Ok(poll(coordinator, &mut HashSet::new(), calls_only).await?.entries)
+}
 
-    let events = client.fetch_events(subs, TIMEOUT).await?;
+/// Other accounts keyed by quorum fingerprint, with the participants of each.
+fn other_accounts(
+    coordinator: &Coordinator,
+    db: &rusqlite::Connection,
+) -> Result<HashMap<String, OtherAccount>> {
+    let mut others = HashMap::new();
+    for account in super::db::list_accounts(db)? {
+        if account.id == coordinator.account_id {
+            continue;
+        }
+        let fingerprint = match super::descriptor::fingerprint(&account.descriptor) {
+            Ok(fp) => fp,
+            Err(e) => {
+                output::warn(format!("Skipping account {}: {e}", account.id));
+                continue;
+            }
+        };
+        let mut peers = HashSet::new();
+        for friend in super::db::list_friends(db, account.id)? {
+            match PublicKey::parse(&friend.npub) {
+                Ok(pk) => {
+                    peers.insert(pk.to_hex());
+                }
+                Err(e) => output::warn(format!("Skipping friend {}: {e}", friend.npub)),
+            }
+        }
+        let calls = 0;
+        others.insert(
+            fingerprint,
+            OtherAccount {
+                account,
+                peers,
+                calls,
+            },
+        );
+    }
+
+    Ok(others)
+}
+
+/// Fetch messages from quorum participants, and from the participants of the quorums of
+/// `others`, newer than the cursor of each relay, from our relays and the relays participants
+/// write to. See [`NostrMessenger::fetch_since`].
+///
+/// Returns the messages along with the new cursor of each relay, which should be stored once
+/// the messages are processed.
+async fn fetch_envelopes(
+    coordinator: &Coordinator,
+    db: &rusqlite::Connection,
+    others: &HashMap<String, OtherAccount>,
+) -> Result<(Vec<Envelope>, Vec<(String, u64)>)> {
+    let client = coordinator.client();
+    match coordinator.relay_hints().await {
+        Ok(hints) => {
+            for url in hints.values().flat_map(|hints| &hints.write) {
+                if let Err(e) = client.add_relay(url).await {
+                    output::warn(format!("Skipping relay {url}: {e}"));
+                }
+            }
+        }
+        Err(e) => output::warn(format!("Failed to fetch relay lists: {e}")),
+    }
+    let messenger = NostrMessenger::new(client.clone(), coordinator.transport).await?;
+    let mut peers: Vec<_> = coordinator.participants().map(|(_, p)| p.pk.to_hex()).collect();
+    for pk in others.values().flat_map(|other| &other.peers) {
+        if !peers.contains(pk) {
+            peers.push(pk.clone());
+        }
+    }
+    let mut envelopes = vec![];
+    let mut cursors = vec![];
 
-    for event in events {
-        if let Kind::TextNote = event.kind {
-            entries.insert(event.id, (event.pubkey, event.content));
+    for url in client.relays().await.into_keys() {
+        let relay = url.to_string();
+        // fall back to the default lookback for a relay we haven't fetched from
+        let since = match inbox::cursor(db, coordinator.account_id, &relay)? {
+            Some(since) => since,
+            None => now().saturating_sub(DEFAULT_LOOKBACK),
+        };
+        let messenger = messenger.clone().with_fetch_relays(vec![url]);
+        // keep the cursor of a relay we couldn't read, so the rest is fetched next time
+        let fetched = match messenger.fetch_since(&peers, since).await {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                output::warn(format!("Failed to fetch from {relay}: {e}"));
+                continue;
+            }
+        };
+        // a note dated in the future mustn't move the cursor past calls yet to come
+        if let Some(newest) = fetched.iter().map(|envelope| envelope.created_at).max() {
+            cursors.push((relay, newest.min(now())));
         }
+        envelopes.extend(fetched);
     }
 
-    Ok(entries)
+    Ok((envelopes, cursors))
 }
 
-/// Decrypt nip44.
-async fn decrypt_raw_entries(
+/// Decrypt the calls among `envelopes`. Plain notes are left out if `calls_only`.
+///
+/// Calls for the quorums of `others` sent by one of their participants are counted, and
+/// reported so they can be fetched with that account.
+///
+/// Errors if a remote signer fails, so the calls are fetched again on the next poll rather
+/// than lost.
+async fn decrypt_envelopes(
     coordinator: &Coordinator,
-    messages: impl IntoIterator<Item = (PublicKey, String)>,
-) -> Result<Vec<ChatEntry>> {
+    db: &rusqlite::Connection,
+    envelopes: impl IntoIterator<Item = Envelope>,
+    others: &mut HashMap<String, OtherAccount>,
+    calls_only: bool,
+) -> Result<Decrypted> {
+    let account_id = coordinator.account_id;
+    let mut reassembler = inbox::reassembler(db, account_id)?;
     let signer = coordinator.signer().await?;
-    let mut ret = vec![];
-
-    // If we see HRP, we read the message fingerprint and check if it matches the current
-    // quorum's FP. When a match is found, we derive the participant from the parsed PID.
-    // If the derived participant's PK matches the current user's PK, we reconstruct the
-    // conversation key according to nip44 and decrypt.
-    for (pk, message) in messages {
-        let alias = coordinator
-            .participants()
-            .filter(|(_id, p)| p.pk == pk)
-            .map(|(_, p)| p.alias.clone())
-            .next()
-            .expect("we subscribed to the pk")
-            .unwrap_or_default();
-        if !message.starts_with(loon::HRP) {
-            ret.push(ChatEntry { alias, message });
-            continue;
+    let my_pk = signer.get_public_key().await?;
+    let me = coordinator
+        .participants()
+        .find(|(_, p)| p.pk == my_pk)
+        .map(|(id, _)| *id);
+    let (mut calls, mut notes) = (vec![], vec![]);
+    // count a call for the quorum of another account sent by one of its participants
+    let mut count = |fingerprint: &str, sender: &str| {
+        if let Some(other) = others.get_mut(fingerprint) {
+            if other.peers.contains(sender) {
+                other.calls += 1;
+            }
         }
+    };
 
-        // parse quorum FP
-        let quorum_fp = &message[5..13];
-        if quorum_fp == coordinator.quorum_fingerprint() {
-            // parse two-digit pid, e.g. '02'
-            let quid: u32 = message[13..15].parse()?;
-
-            // derive recipient p from quorum id
-            // we should get one because the message fp matches the active quorum
-            let participant = coordinator.participants().find(|(pid, _p)| pid.as_u32() == quid);
-
-            if let Some((_pid, participant)) = participant {
-                // parse payload for the intended recipient
-                if participant.pk == signer.get_public_key().await? {
-                    assert!(message.len() > 15);
-                    let payload = &message[15..];
-                    let res = match payload {
-                        "0" => CallTy::Nack,
-                        "1" => CallTy::Ack,
-                        _ => {
-                            let decoded = signer.nip44_decrypt(&pk, payload).await?;
-                            CallTy::Note(decoded)
-                        }
-                    };
-                    ret.push(ChatEntry {
+    // Calls for our quorum addressed to us are decrypted, see `Call::open`. Chunked messages
+    // are passed to the `reassembler` and only decoded once complete. Stale calls and calls
+    // whose nonce was already seen from the sender are rejected.
+    for envelope in envelopes {
+        let Envelope {
+            id: event_id,
+            sender: sender_hex,
+            created_at,
+            expires_at,
+            content: message,
+        } = envelope;
+        let Some((sender, pk, alias)) = coordinator
+            .participants()
+            .find(|(_, p)| p.pk.to_hex() == sender_hex)
+            .map(|(id, p)| (*id, p.pk, p.alias.clone().unwrap_or_default()))
+        else {
+            if let Ok(call) = Call::parse(&message) {
+                count(&call.fingerprint, &sender_hex);
+            }
+            continue;
+        };
+        let opened = match me {
+            Some(me) => {
+                let fingerprint = coordinator.quorum_fingerprint();
+                Call::open(signer.as_ref(), fingerprint, me, &pk, &message).await
+            }
+            // not a participant, so only notes are for us
+            None if !message.starts_with(loon::HRP) => Ok(Opened::Note(message)),
+            None => continue,
+        };
+        let chunk = match opened {
+            Ok(Opened::Note(text)) => {
+                if !calls_only {
+                    notes.push(ChatEntry {
+                        pid: sender,
                         alias,
-                        message: res.to_string(),
+                        event_id,
+                        created_at,
+                        expires_at,
+                        reply_to: None,
+                        broadcast: false,
+                        call: CallTy::Note { text },
                     });
                 }
+                continue;
             }
+            Ok(Opened::OtherQuorum(fingerprint)) => {
+                count(&fingerprint, &sender_hex);
+                continue;
+            }
+            Ok(Opened::NotForUs) => continue,
+            Ok(Opened::Chunk(chunk)) => chunk,
+            // leave the call to be fetched again rather than moving the cursor past it
+            Err(CallError::Signer(e)) => bail!("failed to decrypt call from {alias}: {e}"),
+            // Skip malformed calls
+            Err(e) => {
+                output::warn(format!("Skipping malformed call from {alias}: {e}"));
+                continue;
+            }
+        };
+        let res = reassembler.insert(&sender_hex, chunk.clone(), now());
+        // keep the parts of chunked messages until the whole message is in
+        if chunk.total > 1 {
+            inbox::insert_chunk(db, account_id, &event_id, &sender_hex, &chunk, now())?;
+            if !matches!(res, Ok(None)) {
+                inbox::finish_chunks(db, account_id, &chunk.id)?;
+            }
+        }
+        let payload = match res {
+            Ok(Some(message)) => message,
+            Ok(None) => continue,
+            Err(e) => {
+                output::warn(format!("Skipping malformed call from {alias}: {e}"));
+                continue;
+            }
+        };
+        let payload = match CallPayload::decode(&payload, now()) {
+            Ok(payload) => payload,
+            Err(e) => {
+                output::warn(format!("Skipping call from {alias}: {e}"));
+                continue;
+            }
+        };
+        if !inbox::insert_nonce(db, account_id, sender, &payload.nonce, payload.created_at)? {
+            output::warn(format!("Skipping replayed call from {alias}"));
+            continue;
         }
+        calls.push(ChatEntry {
+            pid: sender,
+            alias,
+            event_id,
+            created_at,
+            // the earlier of the two
+            expires_at: expires_at.into_iter().chain(payload.expires_at).min(),
+            reply_to: payload.parent().map(str::to_string),
+            broadcast: payload.broadcast,
+            call: payload.call,
+        });
+    }
+
+    for id in reassembler.expire(now()) {
+        output::warn(format!("Dropping incomplete message {id}"));
+        inbox::finish_chunks(db, account_id, &id)?;
+    }
+
+    Ok(Decrypted { calls, notes })
+}
+
+/// Fetch and decrypt notes from events not already `seen` or in the inbox, marking them as
+/// seen and adding them to the inbox. The position of each relay is remembered, so the next
+/// poll only asks for newer events. Nothing is stored or marked as seen if the poll fails.
+///
+/// Expired calls, and PSBT requests whose inputs are spent, are archived rather than returned.
+///
+/// Parts of chunked messages are kept in the db until complete or timed out. Calls for the
+/// quorums of other accounts are counted and reported.
+pub async fn poll(
+    coordinator: &Coordinator,
+    seen: &mut HashSet<String>,
+    calls_only: bool,
+) -> Result<Polled> {
+    let mut db = super::db::open()?;
+    let mut others = other_accounts(coordinator, &db)?;
+    let (envelopes, cursors) = fetch_envelopes(coordinator, &db, &others).await?;
+    let known = inbox::event_ids(&db, coordinator.account_id)?;
+
+    // only decrypt new events, once each though several relays return them
+    let mut ids = HashSet::new();
+    let envelopes = envelopes.into_iter().filter(|envelope| {
+        !known.contains(&envelope.id)
+            && !seen.contains(&envelope.id)
+            && ids.insert(envelope.id.clone())
+    });
+
+    // Nonces, parts, inbox rows and cursors are stored together, so a call is never taken
+    // for replayed without being in the inbox.
+    let tx = db.transaction()?;
+    let Decrypted {
+        mut calls,
+        mut notes,
+    } = decrypt_envelopes(coordinator, &tx, envelopes, &mut others, calls_only).await?;
+    inbox::prune_nonces(&tx, coordinator.account_id, now().saturating_sub(CallPayload::MAX_AGE))?;
+    inbox::prune_chunks(&tx, coordinator.account_id, now().saturating_sub(DEFAULT_LOOKBACK))?;
+    inbox::insert(&tx, coordinator.account_id, &calls)?;
+    inbox::insert(&tx, coordinator.account_id, &notes)?;
+    for (relay, since) in cursors {
+        inbox::set_cursor(&tx, coordinator.account_id, &relay, since)?;
+    }
+    tx.commit()?;
+    seen.extend(ids);
+    inbox::archive_expired(coordinator, &db, now())?;
+    calls.retain(|entry| !inbox::is_expired(coordinator, entry, now()));
+    notes.retain(|entry| !inbox::is_expired(coordinator, entry, now()));
+    // plain notes are public, so they aren't news to hooks
+    super::hook::on_calls(coordinator.account_id, &calls).await;
+    let mut entries: Vec<_> = calls.into_iter().chain(notes).collect();
+    entries.sort_by_key(|entry| entry.created_at);
+
+    let mut others: Vec<_> = others
+        .into_values()
+        .filter(|other| other.calls > 0)
+        .map(|OtherAccount { account, calls, .. }| OtherCalls {
+            account_id: account.id,
+            nick: account.nick,
+            calls,
+        })
+        .collect();
+    others.sort_by_key(|other| other.account_id);
+    for other in &others {
+        output::warn(format!(
+            "{} calls for account {} ({}), fetch them with `loon -a {} fetch`",
+            other.calls, other.account_id, other.nick, other.account_id
+        ));
     }
 
-    Ok(ret)
+    Ok(Polled { entries, others })
 }
 
-/// Listens for incoming calls, and writes to a log file.
-// or write to database?
-pub async fn listen(coordinator: &Coordinator) -> Result<()> {
-    let cargo_dir = env!("CARGO_MANIFEST_DIR");
-    let path = format!("{}/chat.log", cargo_dir);
-    let mut f = fs::File::options().append(true).open(&path).await?;
+/// Current unix time in seconds.
+pub fn now() -> u64 {
+    Timestamp::now().as_secs()
+}
 
+/// Listens for incoming calls, and adds them to the inbox.
+pub async fn listen(coordinator: &Coordinator, calls_only: bool) -> Result<()> {
     // keep track of events seen
-    let mut event_ids = HashSet::<EventId>::new();
+    let mut event_ids = HashSet::<String>::new();
 
     loop {
-        let raw_entries = fetch_raw_entries(coordinator).await?;
-
-        // only log new events
-        if !raw_entries.is_empty() {
-            let raw_entries_iter = raw_entries.into_iter().filter_map(|(event, entry)| {
-                if event_ids.contains(&event) {
-                    None
-                } else {
-                    event_ids.insert(event);
-                    Some(entry)
-                }
-            });
-            let chat_entries = decrypt_raw_entries(coordinator, raw_entries_iter).await?;
-            for entry in chat_entries {
-                let content = match entry.message.as_bytes() {
-                    b if b.len() < 1024 => format!("{}: {}\n", entry.alias, entry.message),
-                    _ => "message too long, skipping\n".to_string(),
-                };
-                let _ = f.write(content.as_bytes()).await?;
-            }
-        }
+        let Polled { entries, .. } = poll(coordinator, &mut event_ids, calls_only).await?;
+        print_threads(coordinator, &entries)?;
 
         // refresh on 10s interval
         time::sleep(Duration::from_secs(10)).await;
     }
 }
+
+#[cfg(all(test, feature = "test-relay"))]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex, OnceLock};
+
+    use bdk_chain::bitcoin::{constants, Network};
+    use bdk_chain::keychain_txout::KeychainTxOutIndex;
+    use bdk_chain::local_chain::LocalChain;
+    use bdk_chain::miniscript::{Descriptor, DescriptorPublicKey};
+    use bdk_chain::TxGraph;
+    use clap::Parser;
+    use loon::simplerpc::{self, jsonrpc};
+    use loon::{BdkChangeSet, BdkWallet, Friend, Keychain, TestRelay, Transport};
+    use nostr_sdk::{Keys, ToBech32};
+
+    use super::*;
+    use crate::cli::CallOpt;
+    use crate::cmd::call;
+
+    /// Fingerprint of the quorum of the simulated participants.
+    const FINGERPRINT: &str = "0badf00d";
+
+    const DESC: &str = "wpkh([7d94197e/84h/1h/0h]tpubDCmcN1ucMUfxxabEnLKHzUbjaxg8P4YR4V7mMsfhnsdRJquRyDTudrBmzZhrpV4Z4PH3MjKKFtBk6WkJbEWqL9Vc8E8v1tqFxtFXRY8zEjG/0/*)";
+
+    /// Move to a new temporary directory, where the loon db is created.
+    fn in_temp_dir() {
+        static DIR: OnceLock<()> = OnceLock::new();
+        DIR.get_or_init(|| {
+            let dir = std::env::temp_dir().join(format!("loon-test-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::env::set_current_dir(dir).unwrap();
+        });
+    }
+
+    /// Coordinator of account `account_id` signing with `keys`, connected to `relay` only. The
+    /// participant id of each of the `quorum` is its position plus one.
+    async fn coordinator(
+        relay: &TestRelay,
+        account_id: u32,
+        keys: &Keys,
+        quorum: &[(&str, &Keys)],
+        transport: Transport,
+    ) -> Coordinator {
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(DESC).unwrap();
+        let mut index = KeychainTxOutIndex::<Keychain>::default();
+        assert!(index.insert_descriptor(Keychain::EXTERNAL, desc).unwrap());
+        let genesis = constants::genesis_block(Network::Signet).block_hash();
+        let (chain, _) = LocalChain::from_genesis_hash(genesis);
+        let wallet = BdkWallet {
+            network: Network::Signet,
+            chain,
+            tx_graph: TxGraph::default(),
+            index,
+            stage: BdkChangeSet::default(),
+        };
+        // never called
+        let simple_http = jsonrpc::simple_http::Builder::new()
+            .url("http://127.0.0.1:38332")
+            .unwrap()
+            .build();
+        let client = relay.client(keys.clone()).await.unwrap();
+
+        let mut coordinator = Coordinator {
+            account_id,
+            fingerprint: FINGERPRINT.to_string(),
+            wallet,
+            participants: BTreeMap::new(),
+            client: Arc::new(client),
+            transport,
+            db: Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap())),
+            rpc_client: simplerpc::Client::with_transport(simple_http),
+        };
+        for (i, (alias, keys)) in quorum.iter().enumerate() {
+            let quorum_id = i as u32 + 1;
+            let friend = Friend {
+                account_id,
+                quorum_id,
+                npub: keys.public_key().to_bech32().unwrap(),
+                alias: Some(alias.to_string()),
+            };
+            coordinator.add_participant(quorum_id, friend);
+        }
+
+        coordinator
+    }
+
+    /// Create a call with the `call new` arguments `args` and send it.
+    async fn call_new(coordinator: &Coordinator, args: &[&str]) {
+        let opt = CallOpt::parse_from(["new"].iter().chain(args));
+        let calls = call::new_call(coordinator, &opt, None).await.unwrap();
+        let outcome = call::send(coordinator, &calls, None).await.unwrap();
+        assert!(outcome.failed.is_empty(), "{:?}", outcome.failed);
+        assert_eq!(outcome.sent.len(), calls.len());
+    }
+
+    /// Alice sends Bob a note over a local relay, Bob fetches it and acks it, and Alice
+    /// fetches the ack. Each of them uses the accounts of the given ids.
+    async fn call_new_then_fetch(transport: Transport, alice_id: u32, bob_id: u32) {
+        in_temp_dir();
+        let relay = TestRelay::run().await.unwrap();
+        let (alice_keys, bob_keys) = (Keys::generate(), Keys::generate());
+        let quorum = [("alice", &alice_keys), ("bob", &bob_keys)];
+        let alice = coordinator(&relay, alice_id, &alice_keys, &quorum, transport).await;
+        let bob = coordinator(&relay, bob_id, &bob_keys, &quorum, transport).await;
+        let db = crate::cmd::db::open().unwrap();
+
+        // long enough to be sent in parts
+        let text = "a".repeat(loon::CHUNK_SIZE + 1);
+        call_new(&alice, &["--id", "2", "--note", &text]).await;
+
+        let Polled { entries, .. } = poll(&bob, &mut HashSet::new(), false).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].alias, "alice");
+        assert_eq!(entries[0].call, CallTy::Note { text: text.clone() });
+        let rows = inbox::list(&db, bob_id).unwrap();
+        assert_eq!(rows.len(), 1);
+        let call_id = rows[0].event_id.clone();
+        assert_eq!(call_id, entries[0].event_id);
+        assert_eq!(rows[0].pid, 1);
+        assert!(!rows[0].read && !rows[0].archived);
+        assert_eq!(inbox::chat_entry(&bob, &rows[0]).unwrap().call, CallTy::Note { text });
+
+        // nothing new on the next poll
+        let Polled { entries, .. } = poll(&bob, &mut HashSet::new(), false).await.unwrap();
+        assert!(entries.is_empty());
+        assert_eq!(inbox::list(&db, bob_id).unwrap().len(), 1);
+
+        call_new(&bob, &["--id", "1", "--ack", "--reply-to", &call_id]).await;
+
+        let Polled { entries, .. } = poll(&alice, &mut HashSet::new(), false).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].alias, "bob");
+        let rows = inbox::list(&db, alice_id).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].event_id, entries[0].event_id);
+        assert_eq!(rows[0].pid, 2);
+        assert_eq!(rows[0].reply_to.as_deref(), Some(call_id.as_str()));
+        let ack = CallTy::Ack {
+            ref_event: Some(call_id),
+        };
+        assert_eq!(inbox::chat_entry(&alice, &rows[0]).unwrap().call, ack);
+    }
+
+    #[tokio::test]
+    async fn call_new_then_fetch_note() {
+        call_new_then_fetch(Transport::Note, 1, 2).await;
+    }
+
+    #[tokio::test]
+    async fn call_new_then_fetch_gift_wrap() {
+        call_new_then_fetch(Transport::GiftWrap, 3, 4).await;
+    }
+}
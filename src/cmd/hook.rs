@@ -0,0 +1,210 @@
+use std::fmt;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Duration;
+
+use loon::{CallTy, ChatEntry, Hook, WalletEvent};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::time;
+
+use super::output::{self, ChatInfo, EventInfo};
+use super::rusqlite::{self, named_params};
+use super::{bail, Result};
+
+/// How long a hook may run before it is killed.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Event that runs a hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    /// A new call arrived
+    Call,
+    /// A new PSBT request arrived
+    PsbtRequest,
+    /// The wallet saw a new or changed transaction
+    Tx,
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Call => "call",
+            Self::PsbtRequest => "psbt-request",
+            Self::Tx => "tx",
+        }
+        .fmt(f)
+    }
+}
+
+impl FromStr for HookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "call" => Ok(Self::Call),
+            "psbt-request" => Ok(Self::PsbtRequest),
+            "tx" => Ok(Self::Tx),
+            _ => Err(format!("unknown hook event {s}")),
+        }
+    }
+}
+
+/// Add a hook for `event` to the account, running either `command` or posting to `url`.
+/// Returns the id of the hook.
+pub fn insert(
+    db: &rusqlite::Connection,
+    account_id: u32,
+    event: HookEvent,
+    command: Option<&str>,
+    url: Option<&str>,
+) -> Result<i64> {
+    match (command, url) {
+        (Some(_), None) => {}
+        (None, Some(url)) if url.starts_with("http://") => {}
+        (None, Some(_)) => bail!("webhook url must start with http://"),
+        _ => bail!("a hook needs either a command or a url"),
+    }
+    db.execute(
+        "INSERT INTO hook (account_id, event, command, url) VALUES (:account_id, :event, :command, :url)",
+        named_params! {":account_id": account_id, ":event": event.to_string(), ":command": command, ":url": url},
+    )?;
+
+    Ok(db.last_insert_rowid())
+}
+
+/// List the hooks of the account.
+pub fn list(db: &rusqlite::Connection, account_id: u32) -> Result<Vec<Hook>> {
+    let mut stmt =
+        db.prepare("SELECT id, account_id, event, command, url FROM hook WHERE account_id = ?1")?;
+    let hooks = stmt
+        .query_map([account_id], |row| {
+            Ok(Hook {
+                id: row.get(0)?,
+                account_id: row.get(1)?,
+                event: row.get(2)?,
+                command: row.get(3)?,
+                url: row.get(4)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(hooks)
+}
+
+/// Run the hooks for new calls `entries`, which are the calls decrypted for us rather than
+/// plain notes.
+pub async fn on_calls(account_id: u32, entries: &[ChatEntry]) {
+    for entry in entries {
+        let info = ChatInfo::from(entry);
+        run(account_id, HookEvent::Call, &info).await;
+        if let CallTy::PsbtRequest { .. } = entry.call {
+            run(account_id, HookEvent::PsbtRequest, &info).await;
+        }
+    }
+}
+
+/// Run the hooks for wallet `events` about transactions.
+pub async fn on_wallet_events(account_id: u32, events: &[WalletEvent]) {
+    for event in events {
+        if let WalletEvent::Reorg { .. } = event {
+            continue;
+        }
+        run(account_id, HookEvent::Tx, &EventInfo::from(event)).await;
+    }
+}
+
+/// Run the hooks of the account for `event`, passing them `data` as JSON.
+///
+/// Hooks that fail are reported and otherwise ignored, so they never interrupt the caller.
+pub async fn run(account_id: u32, event: HookEvent, data: &impl Serialize) {
+    let hooks = match super::db::open().and_then(|db| list(&db, account_id)) {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            output::warn(format!("Failed to load hooks: {e}"));
+            return;
+        }
+    };
+    let body = serde_json::json!({ "event": event, "account_id": account_id, "data": data });
+    let body = body.to_string();
+
+    for hook in hooks.iter().filter(|hook| hook.event == event.to_string()) {
+        let res = match (&hook.command, &hook.url) {
+            (Some(command), _) => time::timeout(HOOK_TIMEOUT, exec(command, &body)).await,
+            (None, Some(url)) => time::timeout(HOOK_TIMEOUT, post(url, &body)).await,
+            (None, None) => continue,
+        };
+        match res {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => output::warn(format!("Hook {} failed: {e}", hook.id)),
+            Err(_) => output::warn(format!("Hook {} timed out", hook.id)),
+        }
+    }
+}
+
+/// Run the shell `command` with `body` on stdin.
+///
+/// The output of the command is discarded so it doesn't mix with ours, save for its stderr,
+/// which is part of the error if it fails.
+async fn exec(command: &str, body: &str) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a command may exit without reading the body, e.g. `notify-send`
+        match stdin.write_all(body.as_bytes()).await {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => {}
+        }
+    }
+    let output = child.wait_with_output().await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.trim() {
+        _ if output.status.success() => {}
+        "" => bail!("command {}", output.status),
+        stderr => bail!("command {}: {stderr}", output.status),
+    }
+
+    Ok(())
+}
+
+/// POST `body` as JSON to the `http://` `url`, e.g. a receiver on localhost.
+async fn post(url: &str, body: &str) -> Result<()> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        bail!("webhook url must start with http://");
+    };
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let mut stream = if host.contains(':') {
+        TcpStream::connect(host).await?
+    } else {
+        TcpStream::connect((host, 80)).await?
+    };
+
+    let request = format!(
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await?;
+
+    // e.g. "HTTP/1.1 200 OK"
+    let status_line = String::from_utf8_lossy(&response);
+    let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+    if !status.starts_with('2') {
+        bail!("webhook responded {status}");
+    }
+
+    Ok(())
+}
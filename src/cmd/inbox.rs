@@ -0,0 +1,506 @@
+use std::collections::HashSet;
+
+use loon::{CallTy, ChatEntry, Chunk, Coordinator, InboxEntry, Pid, Reassembler};
+
+use super::output::{self, InboxInfo};
+use super::rusqlite::{self, named_params};
+use super::{bail, Context, Result};
+use crate::cli::InboxSubCmd;
+
+/// Execute inbox operation.
+pub fn execute(coordinator: &Coordinator, subcmd: InboxSubCmd, json: bool) -> Result<()> {
+    let db = super::db::open()?;
+    let account_id = coordinator.account_id;
+
+    match subcmd {
+        InboxSubCmd::List { unread, archived } => {
+            let entries = list(&db, account_id)?
+                .into_iter()
+                .filter(|entry| entry.archived == archived && (!unread || !entry.read))
+                .collect::<Vec<_>>();
+            if json {
+                let entries = entries
+                    .iter()
+                    .map(|entry| inbox_info(coordinator, entry))
+                    .collect::<Result<Vec<_>>>()?;
+                return output::print_json(&entries);
+            }
+            for entry in &entries {
+                let chat = chat_entry(coordinator, entry)?;
+                let flag = if entry.read { ' ' } else { '*' };
+                let summary = match &chat.call {
+                    CallTy::PsbtRequest { memo, .. } => {
+                        format!("PSBT request {}", memo.as_deref().unwrap_or_default())
+                    }
+                    CallTy::PsbtSigned { .. } => "Signed PSBT".to_string(),
+                    call => call.to_string(),
+                };
+                println!("{flag} {} {}: {}", &entry.event_id[..12], chat.alias, summary);
+            }
+        }
+        InboxSubCmd::Read { id } => {
+            let entry = get(&db, account_id, &id)?;
+            set_read(&db, account_id, &entry.event_id)?;
+            if json {
+                return output::print_json(&inbox_info(coordinator, &entry)?);
+            }
+            let chat = chat_entry(coordinator, &entry)?;
+            println!("Event: {}", chat.event_id);
+            println!("From: {} ({})", chat.alias, chat.pid);
+            if chat.broadcast {
+                println!("To: all");
+            }
+            println!("Created at: {}", chat.created_at);
+            if let Some(id) = &chat.reply_to {
+                println!("Reply to: {id}");
+            }
+            println!("{}", chat.call);
+        }
+        InboxSubCmd::Delete { id } => {
+            let entry = get(&db, account_id, &id)?;
+            let ct = db.execute(
+                "DELETE FROM inbox WHERE account_id = ?1 AND event_id = ?2",
+                rusqlite::params![account_id, entry.event_id],
+            )?;
+            if json {
+                output::print_json(&serde_json::json!({ "deleted": ct }))?;
+            } else {
+                println!("Deleted {ct} rows from table inbox");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remember the `nonce` of a call from participant `pid` created at unix time `created_at`.
+///
+/// Returns false if the nonce was already seen from the participant, i.e. the call is
+/// replayed.
+pub fn insert_nonce(
+    db: &rusqlite::Connection,
+    account_id: u32,
+    pid: Pid,
+    nonce: &str,
+    created_at: u64,
+) -> Result<bool> {
+    let ct = db.execute(
+        "INSERT OR IGNORE INTO seen_nonce (account_id, pid, nonce, created_at) VALUES (:account_id, :pid, :nonce, :created_at)",
+        named_params! {":account_id": account_id, ":pid": pid.as_u32(), ":nonce": nonce, ":created_at": created_at},
+    )?;
+
+    Ok(ct == 1)
+}
+
+/// Forget the nonces of calls created before unix time `before`, which are rejected as stale
+/// anyway.
+pub fn prune_nonces(db: &rusqlite::Connection, account_id: u32, before: u64) -> Result<()> {
+    db.execute(
+        "DELETE FROM seen_nonce WHERE account_id = ?1 AND created_at < ?2",
+        rusqlite::params![account_id, before],
+    )?;
+
+    Ok(())
+}
+
+/// Unix time of the newest event fetched from `relay` for the account, if any.
+pub fn cursor(db: &rusqlite::Connection, account_id: u32, relay: &str) -> Result<Option<u64>> {
+    let mut stmt =
+        db.prepare("SELECT since FROM fetch_cursor WHERE account_id = ?1 AND relay = ?2")?;
+    let mut rows = stmt.query_map(rusqlite::params![account_id, relay], |row| row.get(0))?;
+
+    Ok(rows.next().transpose()?)
+}
+
+/// Remember `since` as the newest event fetched from `relay` for the account.
+pub fn set_cursor(
+    db: &rusqlite::Connection,
+    account_id: u32,
+    relay: &str,
+    since: u64,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO fetch_cursor (account_id, relay, since) VALUES (:account_id, :relay, :since)
+        ON CONFLICT (account_id, relay) DO UPDATE SET since = max(since, excluded.since)",
+        named_params! {":account_id": account_id, ":relay": relay, ":since": since},
+    )?;
+
+    Ok(())
+}
+
+/// Ids of the events already in the inbox of the account, or held as parts of a chunked
+/// message.
+pub fn event_ids(db: &rusqlite::Connection, account_id: u32) -> Result<HashSet<String>> {
+    let mut stmt = db.prepare(
+        "SELECT event_id FROM inbox WHERE account_id = ?1
+        UNION SELECT event_id FROM chunk WHERE account_id = ?1",
+    )?;
+    let ids = stmt
+        .query_map([account_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(ids)
+}
+
+/// Remember part of a chunked message from `sender` received at unix time `received_at`, so
+/// it can be reassembled with parts fetched later.
+pub fn insert_chunk(
+    db: &rusqlite::Connection,
+    account_id: u32,
+    event_id: &str,
+    sender: &str,
+    chunk: &Chunk,
+    received_at: u64,
+) -> Result<()> {
+    db.execute(
+        "INSERT OR IGNORE INTO chunk (account_id, event_id, sender, message_id, chunk, received_at) VALUES (:account_id, :event_id, :sender, :message_id, :chunk, :received_at)",
+        named_params! {
+            ":account_id": account_id,
+            ":event_id": event_id,
+            ":sender": sender,
+            ":message_id": chunk.id,
+            ":chunk": serde_json::to_string(chunk)?,
+            ":received_at": received_at,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Mark the parts of message `message_id` as done, once reassembled or dropped.
+pub fn finish_chunks(db: &rusqlite::Connection, account_id: u32, message_id: &str) -> Result<()> {
+    db.execute(
+        "UPDATE chunk SET done = 1 WHERE account_id = ?1 AND message_id = ?2",
+        rusqlite::params![account_id, message_id],
+    )?;
+
+    Ok(())
+}
+
+/// Forget the parts of done messages received before unix time `before`.
+pub fn prune_chunks(db: &rusqlite::Connection, account_id: u32, before: u64) -> Result<()> {
+    db.execute(
+        "DELETE FROM chunk WHERE account_id = ?1 AND done = 1 AND received_at < ?2",
+        rusqlite::params![account_id, before],
+    )?;
+
+    Ok(())
+}
+
+/// [`Reassembler`] holding the parts of the messages of the account that are not done.
+pub fn reassembler(db: &rusqlite::Connection, account_id: u32) -> Result<Reassembler> {
+    let mut stmt = db.prepare(
+        "SELECT sender, chunk, received_at FROM chunk WHERE account_id = ?1 AND done = 0
+        ORDER BY received_at",
+    )?;
+    let rows = stmt
+        .query_map([account_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut reassembler = Reassembler::default();
+    for (sender, chunk, received_at) in rows {
+        let chunk: Chunk = serde_json::from_str(&chunk)?;
+        if let Err(e) = reassembler.insert(&sender, chunk, received_at) {
+            output::warn(format!("Dropping stored part from {sender}: {e}"));
+        }
+    }
+
+    Ok(reassembler)
+}
+
+/// Insert chat `entries` into the inbox of the account, returning the count of rows inserted.
+pub fn insert(db: &rusqlite::Connection, account_id: u32, entries: &[ChatEntry]) -> Result<usize> {
+    let mut stmt = db.prepare(
+        "INSERT OR IGNORE INTO inbox (event_id, account_id, pid, created_at, ty, payload, expires_at, reply_to, broadcast) VALUES (:event_id, :account_id, :pid, :created_at, :ty, :payload, :expires_at, :reply_to, :broadcast)",
+    )?;
+    let mut ct = 0;
+    for entry in entries {
+        ct += stmt.execute(named_params! {
+            ":event_id": entry.event_id,
+            ":account_id": account_id,
+            ":pid": entry.pid.as_u32(),
+            ":created_at": entry.created_at,
+            ":ty": entry.call.name(),
+            ":payload": serde_json::to_string(&entry.call)?,
+            ":expires_at": entry.expires_at,
+            ":reply_to": entry.reply_to,
+            ":broadcast": entry.broadcast,
+        })?;
+    }
+
+    Ok(ct)
+}
+
+/// List the inbox of the account, newest first.
+pub fn list(db: &rusqlite::Connection, account_id: u32) -> Result<Vec<InboxEntry>> {
+    let mut stmt = db.prepare(
+        "SELECT event_id, account_id, pid, created_at, ty, payload, read, expires_at, archived, reply_to, broadcast, response FROM inbox WHERE account_id = ?1 ORDER BY created_at DESC",
+    )?;
+    let entries = stmt
+        .query_map([account_id], |row| {
+            Ok(InboxEntry {
+                event_id: row.get(0)?,
+                account_id: row.get(1)?,
+                pid: row.get(2)?,
+                created_at: row.get(3)?,
+                ty: row.get(4)?,
+                payload: row.get(5)?,
+                read: row.get(6)?,
+                expires_at: row.get(7)?,
+                archived: row.get(8)?,
+                reply_to: row.get(9)?,
+                broadcast: row.get(10)?,
+                response: row.get(11)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(entries)
+}
+
+/// Get the inbox entry whose event id starts with `id`.
+fn get(db: &rusqlite::Connection, account_id: u32, id: &str) -> Result<InboxEntry> {
+    let mut entries = list(db, account_id)?
+        .into_iter()
+        .filter(|entry| entry.event_id.starts_with(id));
+    let Some(entry) = entries.next() else {
+        bail!("no inbox entry with id {id}");
+    };
+    if entries.next().is_some() {
+        bail!("ambiguous id {id}");
+    }
+
+    Ok(entry)
+}
+
+/// Mark the entry with the given `event_id` as read.
+fn set_read(db: &rusqlite::Connection, account_id: u32, event_id: &str) -> Result<()> {
+    db.execute(
+        "UPDATE inbox SET read = 1 WHERE account_id = ?1 AND event_id = ?2",
+        rusqlite::params![account_id, event_id],
+    )?;
+    Ok(())
+}
+
+/// Remember our `response` to the call of the entry with the given `event_id`, i.e. `ack` or
+/// `nack`.
+pub fn set_response(
+    db: &rusqlite::Connection,
+    account_id: u32,
+    event_id: &str,
+    response: &str,
+) -> Result<()> {
+    db.execute(
+        "UPDATE inbox SET response = ?1 WHERE account_id = ?2 AND event_id = ?3",
+        rusqlite::params![response, account_id, event_id],
+    )?;
+
+    Ok(())
+}
+
+/// Whether the call of a chat `entry` is expired as of unix time `now`, or is a PSBT request
+/// whose inputs are already spent on chain.
+pub fn is_expired(coordinator: &Coordinator, entry: &ChatEntry, now: u64) -> bool {
+    if entry.expires_at.is_some_and(|t| t <= now) {
+        return true;
+    }
+    match &entry.call {
+        CallTy::PsbtRequest { psbt, .. } => coordinator.wallet.is_psbt_spent(psbt),
+        _ => false,
+    }
+}
+
+/// Archive the expired entries of the inbox, see [`is_expired`]. Returns the count of entries
+/// archived. Entries that can't be read are reported and skipped.
+pub fn archive_expired(
+    coordinator: &Coordinator,
+    db: &rusqlite::Connection,
+    now: u64,
+) -> Result<usize> {
+    let mut ct = 0;
+    for entry in list(db, coordinator.account_id)? {
+        if entry.archived {
+            continue;
+        }
+        // a bad row shouldn't keep the rest from being archived
+        let chat = match chat_entry(coordinator, &entry) {
+            Ok(chat) => chat,
+            Err(e) => {
+                output::warn(format!("Skipping inbox entry {}: {e:#}", entry.event_id));
+                continue;
+            }
+        };
+        if !is_expired(coordinator, &chat, now) {
+            continue;
+        }
+        ct += db.execute(
+            "UPDATE inbox SET archived = 1 WHERE account_id = ?1 AND event_id = ?2",
+            rusqlite::params![coordinator.account_id, entry.event_id],
+        )?;
+    }
+
+    Ok(ct)
+}
+
+/// Get the chat entry of an inbox `entry`.
+pub fn chat_entry(coordinator: &Coordinator, entry: &InboxEntry) -> Result<ChatEntry> {
+    let pid = entry.pid.into();
+    let alias = coordinator
+        .participants
+        .get(&pid)
+        .and_then(|p| p.alias.clone())
+        .unwrap_or_default();
+    let call = serde_json::from_str(&entry.payload)
+        .with_context(|| format!("invalid payload of {}", entry.event_id))?;
+
+    Ok(ChatEntry {
+        pid,
+        alias,
+        event_id: entry.event_id.clone(),
+        created_at: entry.created_at,
+        expires_at: entry.expires_at,
+        reply_to: entry.reply_to.clone(),
+        broadcast: entry.broadcast,
+        call,
+    })
+}
+
+fn inbox_info(coordinator: &Coordinator, entry: &InboxEntry) -> Result<InboxInfo> {
+    Ok(InboxInfo {
+        chat: (&chat_entry(coordinator, entry)?).into(),
+        read: entry.read,
+        archived: entry.archived,
+        response: entry.response.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> rusqlite::Connection {
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        crate::cmd::db::migrate(&db).unwrap();
+        db
+    }
+
+    #[test]
+    fn duplicate_nonce_from_same_sender_is_replayed() {
+        let db = db();
+        let pid = Pid::from(1);
+        assert!(insert_nonce(&db, 1, pid, "aa", 100).unwrap());
+        assert!(!insert_nonce(&db, 1, pid, "aa", 100).unwrap());
+        // whatever the claimed time of creation
+        assert!(!insert_nonce(&db, 1, pid, "aa", 200).unwrap());
+    }
+
+    #[test]
+    fn same_nonce_from_different_senders() {
+        let db = db();
+        assert!(insert_nonce(&db, 1, Pid::from(1), "aa", 100).unwrap());
+        assert!(insert_nonce(&db, 1, Pid::from(2), "aa", 100).unwrap());
+        // and from the same participant id of another account
+        assert!(insert_nonce(&db, 2, Pid::from(1), "aa", 100).unwrap());
+    }
+
+    #[test]
+    fn prune_nonces_created_before() {
+        let db = db();
+        let pid = Pid::from(1);
+        insert_nonce(&db, 1, pid, "aa", 100).unwrap();
+        insert_nonce(&db, 1, pid, "bb", 101).unwrap();
+        prune_nonces(&db, 1, 101).unwrap();
+        assert!(insert_nonce(&db, 1, pid, "aa", 100).unwrap());
+        assert!(!insert_nonce(&db, 1, pid, "bb", 101).unwrap());
+    }
+
+    fn note(event_id: &str) -> ChatEntry {
+        ChatEntry {
+            pid: Pid::from(1),
+            alias: "alice".to_string(),
+            event_id: event_id.to_string(),
+            created_at: 100,
+            expires_at: None,
+            reply_to: None,
+            broadcast: false,
+            call: CallTy::Note {
+                text: "hello".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn same_note_for_two_accounts() {
+        let db = db();
+        assert_eq!(insert(&db, 1, &[note("e0")]).unwrap(), 1);
+        assert_eq!(insert(&db, 2, &[note("e0")]).unwrap(), 1);
+        assert_eq!(insert(&db, 2, &[note("e0")]).unwrap(), 0);
+        assert!(event_ids(&db, 2).unwrap().contains("e0"));
+
+        // reading or deleting it for one account leaves the other alone
+        set_read(&db, 1, "e0").unwrap();
+        assert!(list(&db, 1).unwrap()[0].read);
+        assert!(!list(&db, 2).unwrap()[0].read);
+    }
+
+    #[test]
+    fn inbox_keyed_by_event_id_is_migrated() {
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE inbox (
+                event_id TEXT PRIMARY KEY,
+                account_id INTEGER NOT NULL,
+                pid INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                ty TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                read INTEGER NOT NULL DEFAULT 0
+            );
+            INSERT INTO inbox (event_id, account_id, pid, created_at, ty, payload, read)
+                VALUES ('e0', 1, 1, 100, 'note', '{\"type\":\"note\",\"text\":\"hello\"}', 1);",
+        )
+        .unwrap();
+        crate::cmd::db::migrate(&db).unwrap();
+
+        let entries = list(&db, 1).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].event_id, "e0");
+        assert!(entries[0].read);
+        assert_eq!(insert(&db, 2, &[note("e0")]).unwrap(), 1);
+    }
+
+    #[test]
+    fn chunks_are_kept_across_polls() {
+        let db = db();
+        let message = "a".repeat(loon::CHUNK_SIZE + 1);
+        let chunks = Chunk::split(&message).unwrap();
+        assert_eq!(chunks.len(), 2);
+        let id = chunks[0].id.clone();
+
+        // first poll sees only the first part
+        let mut first = reassembler(&db, 1).unwrap();
+        assert_eq!(first.insert("alice", chunks[0].clone(), 100).unwrap(), None);
+        insert_chunk(&db, 1, "e0", "alice", &chunks[0], 100).unwrap();
+        assert!(event_ids(&db, 1).unwrap().contains("e0"));
+
+        // the next poll starts from what is stored
+        let mut next = reassembler(&db, 1).unwrap();
+        assert_eq!(next.pending(), 1);
+        let res = next.insert("alice", chunks[1].clone(), 200).unwrap();
+        assert_eq!(res.as_deref(), Some(message.as_str()));
+        insert_chunk(&db, 1, "e1", "alice", &chunks[1], 200).unwrap();
+        finish_chunks(&db, 1, &id).unwrap();
+        assert_eq!(reassembler(&db, 1).unwrap().pending(), 0);
+
+        // not for other accounts
+        insert_chunk(&db, 2, "e0", "alice", &chunks[0], 100).unwrap();
+        assert_eq!(reassembler(&db, 1).unwrap().pending(), 0);
+
+        prune_chunks(&db, 1, 200).unwrap();
+        let ids = event_ids(&db, 1).unwrap();
+        assert!(!ids.contains("e0"));
+        assert!(ids.contains("e1"));
+    }
+}
@@ -0,0 +1,114 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use nostr_sdk::prelude::*;
+
+use super::output;
+use super::{bail, Context, Result};
+use crate::cli::KeySubCmd;
+
+/// Scrypt cost parameter of keys in the keystore, as recommended by NIP-49.
+const LOG_N: u8 = 16;
+
+/// Execute keystore operation for the account with the given `account_id`.
+pub fn execute(account_id: u32, subcmd: &KeySubCmd, json: bool) -> Result<()> {
+    let path = keyfile_path(account_id);
+
+    let keys = match subcmd {
+        KeySubCmd::Generate { force } => {
+            check_overwrite(&path, *force)?;
+            Keys::generate()
+        }
+        KeySubCmd::Import { force } => {
+            check_overwrite(&path, *force)?;
+            // read from the terminal so the nsec stays out of the shell history
+            let nsec = rpassword::prompt_password("nsec: ")?;
+            Keys::parse(nsec.trim()).context("invalid nsec")?
+        }
+        KeySubCmd::Show => super::signer::read_keyfile(&path)?,
+    };
+    if !matches!(subcmd, KeySubCmd::Show) {
+        let passphrase = rpassword::prompt_password("New passphrase: ")?;
+        if passphrase != rpassword::prompt_password("Repeat passphrase: ")? {
+            bail!("passphrases do not match");
+        }
+        write_keyfile(&path, &keys, &passphrase)?;
+    }
+
+    let npub = keys.public_key().to_bech32()?;
+    if json {
+        output::print_json(&serde_json::json!({ "npub": npub, "path": path }))?;
+    } else {
+        println!("{npub}");
+        println!("Keyfile: {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Path of the encrypted key of the account in the keystore.
+pub fn keyfile_path(account_id: u32) -> PathBuf {
+    PathBuf::from(loon::KEYSTORE_DIR).join(format!("{account_id}.ncryptsec"))
+}
+
+/// Path of the app keys the account uses to talk to its NIP-46 remote signer.
+#[cfg(feature = "bunker")]
+pub fn bunker_keys_path(account_id: u32) -> PathBuf {
+    PathBuf::from(loon::KEYSTORE_DIR).join(format!("{account_id}.bunker"))
+}
+
+/// App keys of the account for its NIP-46 remote signer, generated and stored in the
+/// keystore on first use so the bunker keeps recognizing them.
+///
+/// These keys only identify the client to the bunker, so they're stored unencrypted, readable
+/// by the owner only.
+#[cfg(feature = "bunker")]
+pub fn bunker_keys(account_id: u32) -> Result<Keys> {
+    let path = bunker_keys_path(account_id);
+    if path.exists() {
+        let secret = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        return Keys::parse(secret.trim()).context("invalid bunker keys");
+    }
+
+    let keys = Keys::generate();
+    write_private(&path, keys.secret_key().to_secret_hex().as_bytes())?;
+
+    Ok(keys)
+}
+
+/// Errors if a key exists at `path`, unless `force`.
+fn check_overwrite(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!("{} already exists, use --force to replace it", path.display());
+    }
+    Ok(())
+}
+
+/// Encrypt the secret key of `keys` with `passphrase` per NIP-49, and write it to `path`.
+fn write_keyfile(path: &Path, keys: &Keys, passphrase: &str) -> Result<()> {
+    let encrypted =
+        EncryptedSecretKey::new(keys.secret_key(), passphrase, LOG_N, KeySecurity::Medium)?;
+    write_private(path, encrypted.to_bech32()?.as_bytes())
+}
+
+/// Write `contents` to the file at `path`, readable by the owner only.
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .with_context(|| format!("failed to write {}", path.display()))?
+        .write_all(contents)?;
+
+    Ok(())
+}
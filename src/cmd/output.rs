@@ -0,0 +1,420 @@
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+use bdk_chain::{bdk_core::ConfirmationBlockTime, bitcoin, ChainPosition, FullTxOut};
+use bitcoin::{Address, Psbt};
+use serde::Serialize;
+
+use loon::{Account, BdkWallet, CallTy, Keychain, WalletEvent};
+
+use super::Result;
+
+/// Print `value` to stdout as JSON.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Diagnostics kept rather than printed, while they are collected.
+static DIAGNOSTICS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// Report a diagnostic `message` on stderr, or keep it while diagnostics are collected. See
+/// [`collect_diagnostics`].
+pub fn warn(message: impl fmt::Display) {
+    let mut diagnostics = DIAGNOSTICS.lock().unwrap_or_else(PoisonError::into_inner);
+    match diagnostics.as_mut() {
+        Some(diagnostics) => diagnostics.push(message.to_string()),
+        None => eprintln!("{message}"),
+    }
+}
+
+/// Keep diagnostics from now on rather than print them, e.g. while the TUI draws on the
+/// terminal. They are then read with [`take_diagnostics`].
+#[cfg(feature = "tui")]
+pub fn collect_diagnostics() {
+    let mut diagnostics = DIAGNOSTICS.lock().unwrap_or_else(PoisonError::into_inner);
+    diagnostics.get_or_insert_with(Vec::new);
+}
+
+/// Take the diagnostics kept since the last call, oldest first.
+#[cfg(feature = "tui")]
+pub fn take_diagnostics() -> Vec<String> {
+    let mut diagnostics = DIAGNOSTICS.lock().unwrap_or_else(PoisonError::into_inner);
+    diagnostics.as_mut().map(std::mem::take).unwrap_or_default()
+}
+
+/// A wallet address.
+#[derive(Debug, Serialize)]
+pub struct AddressInfo {
+    pub keychain: u8,
+    pub index: u32,
+    pub address: String,
+    /// Whether the address has been used, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used: Option<bool>,
+}
+
+impl AddressInfo {
+    pub fn new(keychain: Keychain, index: u32, address: &Address) -> Self {
+        Self {
+            keychain: keychain.0,
+            index,
+            address: address.to_string(),
+            used: None,
+        }
+    }
+}
+
+/// A wallet tx output.
+#[derive(Debug, Serialize)]
+pub struct TxOutInfo {
+    pub keychain: u8,
+    pub index: u32,
+    pub address: String,
+    /// Value in satoshis
+    pub value: u64,
+    pub outpoint: String,
+    pub spent: bool,
+    /// Confirmation height, if confirmed
+    pub confirmation_height: Option<u32>,
+}
+
+impl TxOutInfo {
+    pub fn new(
+        wallet: &BdkWallet,
+        (keychain, index): (Keychain, u32),
+        txo: &FullTxOut<ConfirmationBlockTime>,
+    ) -> Result<Self> {
+        Ok(Self {
+            keychain: keychain.0,
+            index,
+            address: Address::from_script(&txo.txout.script_pubkey, wallet.network)?.to_string(),
+            value: txo.txout.value.to_sat(),
+            outpoint: txo.outpoint.to_string(),
+            spent: txo.spent_by.is_some(),
+            confirmation_height: confirmation(&txo.chain_position).map(|a| a.block_id.height),
+        })
+    }
+}
+
+/// Wallet balance in satoshis.
+#[derive(Debug, Serialize)]
+pub struct BalanceInfo {
+    pub immature: u64,
+    pub trusted_pending: u64,
+    pub untrusted_pending: u64,
+    pub confirmed: u64,
+    pub total: u64,
+    pub unspent: Vec<TxOutInfo>,
+}
+
+impl BalanceInfo {
+    pub fn new(wallet: &BdkWallet) -> Result<Self> {
+        let balance = wallet.balance();
+        let unspent = wallet
+            .list_unspent()
+            .map(|(indexed, txo)| TxOutInfo::new(wallet, indexed, &txo))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            immature: balance.immature.to_sat(),
+            trusted_pending: balance.trusted_pending.to_sat(),
+            untrusted_pending: balance.untrusted_pending.to_sat(),
+            confirmed: balance.confirmed.to_sat(),
+            total: balance.total().to_sat(),
+            unspent,
+        })
+    }
+}
+
+/// A wallet transaction.
+#[derive(Debug, Serialize)]
+pub struct TxInfo {
+    pub txid: String,
+    /// Value sent from the wallet in satoshis
+    pub sent: u64,
+    /// Value received by the wallet in satoshis
+    pub received: u64,
+    /// Confirmation height, if confirmed
+    pub confirmation_height: Option<u32>,
+    /// Confirmation time, if confirmed
+    pub confirmation_time: Option<u64>,
+}
+
+impl TxInfo {
+    /// List the wallet transactions.
+    pub fn list(wallet: &BdkWallet) -> Vec<Self> {
+        wallet
+            .transactions()
+            .map(|canon_tx| {
+                let (sent, received) = wallet.index.sent_and_received(&canon_tx.tx_node.tx, ..);
+                let anchor = confirmation(&canon_tx.chain_position);
+                Self {
+                    txid: canon_tx.tx_node.txid.to_string(),
+                    sent: sent.to_sat(),
+                    received: received.to_sat(),
+                    confirmation_height: anchor.map(|a| a.block_id.height),
+                    confirmation_time: anchor.map(|a| a.confirmation_time),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A PSBT.
+#[derive(Debug, Serialize)]
+pub struct PsbtInfo {
+    /// Base64 encoded PSBT
+    pub psbt: String,
+    /// Txid of the unsigned transaction
+    pub txid: String,
+    /// Fee in satoshis, if known
+    pub fee: Option<u64>,
+}
+
+impl From<&Psbt> for PsbtInfo {
+    fn from(psbt: &Psbt) -> Self {
+        Self {
+            psbt: psbt.to_string(),
+            txid: psbt.unsigned_tx.compute_txid().to_string(),
+            fee: psbt.fee().ok().map(|fee| fee.to_sat()),
+        }
+    }
+}
+
+/// Details of a PSBT as they relate to the wallet.
+#[derive(Debug, Serialize)]
+pub struct PsbtDetail {
+    /// Txid of the unsigned transaction
+    pub txid: String,
+    /// Fee in satoshis, if known
+    pub fee: Option<u64>,
+    pub inputs: Vec<PsbtInputInfo>,
+    pub outputs: Vec<PsbtOutputInfo>,
+}
+
+/// An input of a PSBT.
+#[derive(Debug, Serialize)]
+pub struct PsbtInputInfo {
+    pub outpoint: String,
+    /// Value of the previous output in satoshis, if known
+    pub value: Option<u64>,
+    /// Count of signatures present
+    pub signatures: usize,
+    /// Whether the previous output belongs to the wallet
+    pub is_mine: bool,
+}
+
+/// An output of a PSBT.
+#[derive(Debug, Serialize)]
+pub struct PsbtOutputInfo {
+    pub address: Option<String>,
+    /// Value in satoshis
+    pub value: u64,
+    /// Whether the output belongs to the wallet
+    pub is_mine: bool,
+}
+
+impl PsbtDetail {
+    pub fn new(wallet: &BdkWallet, psbt: &Psbt) -> Self {
+        let tx = &psbt.unsigned_tx;
+
+        let inputs = tx
+            .input
+            .iter()
+            .zip(&psbt.inputs)
+            .map(|(txin, input)| {
+                let prevout = input.witness_utxo.clone().or_else(|| {
+                    let prev_tx = input.non_witness_utxo.as_ref()?;
+                    prev_tx.output.get(txin.previous_output.vout as usize).cloned()
+                });
+                PsbtInputInfo {
+                    outpoint: txin.previous_output.to_string(),
+                    value: prevout.as_ref().map(|txout| txout.value.to_sat()),
+                    signatures: input.partial_sigs.len()
+                        + input.tap_script_sigs.len()
+                        + usize::from(input.tap_key_sig.is_some()),
+                    is_mine: prevout.is_some_and(|txout| {
+                        wallet.index.index_of_spk(txout.script_pubkey).is_some()
+                    }),
+                }
+            })
+            .collect();
+
+        let outputs = tx
+            .output
+            .iter()
+            .map(|txout| PsbtOutputInfo {
+                address: Address::from_script(&txout.script_pubkey, wallet.network)
+                    .ok()
+                    .map(|addr| addr.to_string()),
+                value: txout.value.to_sat(),
+                is_mine: wallet.index.index_of_spk(txout.script_pubkey.clone()).is_some(),
+            })
+            .collect();
+
+        Self {
+            txid: tx.compute_txid().to_string(),
+            fee: psbt.fee().ok().map(|fee| fee.to_sat()),
+            inputs,
+            outputs,
+        }
+    }
+}
+
+/// A wallet event.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventInfo {
+    Reorg {
+        old_height: u32,
+        old_hash: String,
+        new_height: u32,
+        new_hash: String,
+    },
+    PaymentReceived {
+        txid: String,
+        amount: u64,
+    },
+    TxConfirmed {
+        txid: String,
+        height: u32,
+    },
+    TxUnconfirmed {
+        txid: String,
+    },
+    TxReplaced {
+        txid: String,
+        replaced_by: Vec<String>,
+    },
+    TxDropped {
+        txid: String,
+    },
+}
+
+impl From<&WalletEvent> for EventInfo {
+    fn from(event: &WalletEvent) -> Self {
+        match event {
+            WalletEvent::Reorg { old_tip, new_tip } => Self::Reorg {
+                old_height: old_tip.height,
+                old_hash: old_tip.hash.to_string(),
+                new_height: new_tip.height,
+                new_hash: new_tip.hash.to_string(),
+            },
+            WalletEvent::PaymentReceived { txid, amount } => Self::PaymentReceived {
+                txid: txid.to_string(),
+                amount: amount.to_sat(),
+            },
+            WalletEvent::TxConfirmed { txid, anchor } => Self::TxConfirmed {
+                txid: txid.to_string(),
+                height: anchor.block_id.height,
+            },
+            WalletEvent::TxUnconfirmed { txid } => Self::TxUnconfirmed {
+                txid: txid.to_string(),
+            },
+            WalletEvent::TxReplaced { txid, replaced_by } => Self::TxReplaced {
+                txid: txid.to_string(),
+                replaced_by: replaced_by.iter().map(|txid| txid.to_string()).collect(),
+            },
+            WalletEvent::TxDropped { txid } => Self::TxDropped {
+                txid: txid.to_string(),
+            },
+        }
+    }
+}
+
+/// Result of a wallet sync.
+#[derive(Debug, Serialize)]
+pub struct SyncInfo {
+    pub height: u32,
+    pub hash: String,
+    pub events: Vec<EventInfo>,
+}
+
+/// A quorum account.
+#[derive(Debug, Serialize)]
+pub struct AccountInfo {
+    pub id: u32,
+    pub network: String,
+    pub nick: String,
+    pub descriptor: String,
+    pub transport: String,
+}
+
+impl From<&Account> for AccountInfo {
+    fn from(account: &Account) -> Self {
+        Self {
+            id: account.id,
+            network: account.network.clone(),
+            nick: account.nick.clone(),
+            descriptor: account.descriptor.clone(),
+            transport: account.transport.clone(),
+        }
+    }
+}
+
+/// Calls fetched for the quorum of another account.
+#[derive(Debug, Serialize)]
+pub struct OtherCalls {
+    pub account_id: u32,
+    pub nick: String,
+    /// Count of calls from participants of the account
+    pub calls: usize,
+}
+
+/// A chat entry.
+#[derive(Debug, Serialize)]
+pub struct ChatInfo {
+    /// Sender participant id
+    pub pid: u32,
+    pub alias: String,
+    pub event_id: String,
+    /// Unix time the event was created
+    pub created_at: u64,
+    /// Unix time after which the call is expired, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Id of the event this call replies to, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    /// Whether the call is broadcast to every participant
+    pub broadcast: bool,
+    /// Human readable message
+    pub message: String,
+    pub call: CallTy,
+}
+
+impl From<&loon::ChatEntry> for ChatInfo {
+    fn from(entry: &loon::ChatEntry) -> Self {
+        Self {
+            pid: entry.pid.as_u32(),
+            alias: entry.alias.clone(),
+            event_id: entry.event_id.clone(),
+            created_at: entry.created_at,
+            expires_at: entry.expires_at,
+            reply_to: entry.reply_to.clone(),
+            broadcast: entry.broadcast,
+            message: entry.call.to_string(),
+            call: entry.call.clone(),
+        }
+    }
+}
+
+/// An inbox entry.
+#[derive(Debug, Serialize)]
+pub struct InboxInfo {
+    #[serde(flatten)]
+    pub chat: ChatInfo,
+    pub read: bool,
+    pub archived: bool,
+    /// Our response to the call, `ack` or `nack`, if any
+    pub response: Option<String>,
+}
+
+/// Confirmation anchor of a chain position, if confirmed.
+fn confirmation(pos: &ChainPosition<ConfirmationBlockTime>) -> Option<ConfirmationBlockTime> {
+    match pos {
+        ChainPosition::Confirmed { anchor, .. } => Some(*anchor),
+        ChainPosition::Unconfirmed { .. } => None,
+    }
+}
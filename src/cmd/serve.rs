@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bdk_chain::bitcoin::{Address, Amount, FeeRate, Psbt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::LocalSet;
+use tokio::time;
+
+use loon::{
+    rand::{self, Fill},
+    Coordinator, Keychain,
+};
+
+use super::output::{
+    AccountInfo, AddressInfo, BalanceInfo, EventInfo, PsbtDetail, PsbtInfo, SyncInfo, TxInfo,
+    TxOutInfo,
+};
+use super::{bail, Result};
+use crate::cli::ServeOpt;
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY_LEN: usize = 1 << 20;
+
+/// How long to wait on a client to send its request.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Feerate used to create a PSBT if none is given (sat/vb).
+const DEFAULT_FEERATE: f32 = 1.2;
+
+/// An HTTP request.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    /// Bearer token of the `Authorization` header
+    token: Option<String>,
+    body: Vec<u8>,
+}
+
+/// An HTTP response.
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: impl Serialize) -> Result<Self> {
+        Ok(Self {
+            status: 200,
+            body: serde_json::to_value(body)?,
+        })
+    }
+
+    fn error(status: u16, e: impl fmt::Display) -> Self {
+        Self {
+            status,
+            body: json!({ "error": e.to_string() }),
+        }
+    }
+}
+
+/// Request body of `POST /accounts`.
+#[derive(Debug, Deserialize)]
+struct NewAccount {
+    network: String,
+    nick: String,
+    descriptor: String,
+    transport: Option<String>,
+}
+
+/// Request body of `POST /friends`.
+#[derive(Debug, Deserialize)]
+struct NewFriend {
+    account_id: u32,
+    quorum_id: u32,
+    npub: String,
+    alias: Option<String>,
+}
+
+/// Request body of `POST /psbt`.
+#[derive(Debug, Deserialize)]
+struct NewPsbt {
+    address: String,
+    /// Amount in satoshis
+    amount: u64,
+    /// Feerate in sat/vb
+    feerate: Option<f32>,
+    #[serde(default)]
+    sweep: bool,
+}
+
+/// Request body of `POST /psbt/inspect`.
+#[derive(Debug, Deserialize)]
+struct InspectPsbt {
+    psbt: String,
+}
+
+/// Request body of `POST /psbt/combine`.
+#[derive(Debug, Deserialize)]
+struct CombinePsbt {
+    psbts: Vec<String>,
+}
+
+/// Request body of `POST /calls`.
+#[cfg(feature = "nostr-sdk")]
+#[derive(Debug, Deserialize)]
+struct NewCall {
+    id: Option<u32>,
+    alias: Option<String>,
+    #[serde(default)]
+    all: bool,
+    note: Option<String>,
+    psbt: Option<String>,
+    #[serde(default)]
+    signed: bool,
+    memo: Option<String>,
+    #[serde(default)]
+    ack: bool,
+    #[serde(default)]
+    nack: bool,
+    reason: Option<String>,
+    ref_event: Option<String>,
+    reply_to: Option<String>,
+    ttl: Option<u64>,
+}
+
+/// Serve the coordinator over a localhost HTTP JSON API until interrupted.
+///
+/// Every request must carry the token found in the token file as a bearer token, e.g.
+/// `Authorization: Bearer <token>`. The token file is created if it doesn't exist.
+///
+/// Each request is served by a task of its own, so a slow one, e.g. a sync, doesn't hold up
+/// the others.
+pub async fn run(coordinator: Coordinator, opt: ServeOpt) -> Result<()> {
+    let token: Arc<str> = load_or_create_token(&opt.token_file)?.into();
+    let listener = TcpListener::bind(("127.0.0.1", opt.port)).await?;
+    println!("Listening on http://127.0.0.1:{}", opt.port);
+    println!("Token file: {}", opt.token_file);
+
+    let coordinator = Arc::new(Mutex::new(coordinator));
+    // requests hold the db across awaits, so their tasks are local to this thread
+    let tasks = LocalSet::new();
+    tasks
+        .run_until(async {
+            loop {
+                tokio::select! {
+                    res = listener.accept() => {
+                        let (stream, _) = res?;
+                        let (coordinator, token) = (coordinator.clone(), token.clone());
+                        tokio::task::spawn_local(async move {
+                            if let Err(e) = serve(&coordinator, &token, stream).await {
+                                eprintln!("Request failed: {e}");
+                            }
+                        });
+                    }
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await
+}
+
+/// Read the API token from `path`, or create a new random token if none exists.
+fn load_or_create_token(path: &str) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut buf = [0x00; 32];
+    buf.try_fill(&mut rand::thread_rng())?;
+    let token: String = buf.iter().map(|b| format!("{b:02x}")).collect();
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(token.as_bytes())?;
+
+    Ok(token)
+}
+
+/// Respond to a single request read from `stream`.
+async fn serve(
+    coordinator: &Arc<Mutex<Coordinator>>,
+    token: &str,
+    mut stream: TcpStream,
+) -> Result<()> {
+    let request = match time::timeout(CLIENT_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => return write_response(&mut stream, Response::error(400, e)).await,
+        Err(_) => {
+            let response = Response::error(408, "request timed out");
+            return write_response(&mut stream, response).await;
+        }
+    };
+
+    let is_authorized = request
+        .token
+        .as_deref()
+        .is_some_and(|t| constant_time_eq(t.as_bytes(), token.as_bytes()));
+
+    let response = if !is_authorized {
+        Response::error(401, "unauthorized")
+    } else {
+        route(coordinator, &request)
+            .await
+            .unwrap_or_else(|e| Response::error(400, e))
+    };
+
+    write_response(&mut stream, response).await
+}
+
+/// Read an HTTP request from `stream`.
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+
+    // Request line
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        bail!("malformed request line");
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    // Headers
+    let mut token = None;
+    let mut content_len = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "authorization" => token = value.strip_prefix("Bearer ").map(str::to_string),
+                "content-length" => content_len = value.parse()?,
+                _ => {}
+            }
+        }
+    }
+
+    // Body
+    if content_len > MAX_BODY_LEN {
+        bail!("request body too large");
+    }
+    let mut body = vec![0x00; content_len];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        token,
+        body,
+    })
+}
+
+/// Write an HTTP `response` to `stream`.
+async fn write_response(stream: &mut TcpStream, response: Response) -> Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        502 => "Bad Gateway",
+        _ => "",
+    };
+    let body = serde_json::to_string(&response.body)?;
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        body.len(),
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+
+    Ok(())
+}
+
+/// Handle an authorized `request`.
+async fn route(coordinator: &Arc<Mutex<Coordinator>>, request: &Request) -> Result<Response> {
+    let body = &request.body;
+
+    // Sync to chain tip
+    if (request.method.as_str(), request.path.as_str()) == ("POST", "/sync") {
+        return sync(coordinator.clone()).await;
+    }
+
+    let mut coordinator = coordinator.lock().await;
+    let coordinator = &mut *coordinator;
+    match (request.method.as_str(), request.path.as_str()) {
+        // Sync status
+        ("GET", "/status") => {
+            let tip = coordinator.wallet().tip();
+            Response::ok(json!({
+                "network": coordinator.network().to_string(),
+                "height": tip.height(),
+                "hash": tip.hash().to_string(),
+            }))
+        }
+        // Balance
+        ("GET", "/balance") => Response::ok(BalanceInfo::new(coordinator.wallet())?),
+        // List revealed addresses of a keychain, by default the external keychain
+        ("GET", "/addresses") => {
+            let keychain: Keychain = match request.query.get("keychain") {
+                Some(k) => k.parse::<u8>()?.into(),
+                None => Keychain::EXTERNAL,
+            };
+            let wallet = coordinator.wallet();
+            let addrs = wallet
+                .index
+                .revealed_keychain_spks(keychain)
+                .map(|(index, spk)| -> Result<_> {
+                    let addr = Address::from_script(&spk, wallet.network)?;
+                    Ok(AddressInfo {
+                        used: Some(wallet.index.is_used(keychain, index)),
+                        ..AddressInfo::new(keychain, index, &addr)
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Response::ok(addrs)
+        }
+        // Reveal a new address
+        ("POST", "/addresses") => {
+            let Some(((keychain, index), addr)) = coordinator.wallet.reveal_next_address() else {
+                bail!("failed to reveal address");
+            };
+            coordinator.persist()?;
+            Response::ok(AddressInfo::new(keychain, index, &addr))
+        }
+        // List unspent
+        ("GET", "/utxos") => {
+            let wallet = coordinator.wallet();
+            let utxos = wallet
+                .list_unspent()
+                .map(|(indexed, txo)| TxOutInfo::new(wallet, indexed, &txo))
+                .collect::<Result<Vec<_>>>()?;
+            Response::ok(utxos)
+        }
+        // List transactions
+        ("GET", "/transactions") => Response::ok(TxInfo::list(coordinator.wallet())),
+        // Create PSBT
+        ("POST", "/psbt") => {
+            let params: NewPsbt = serde_json::from_slice(body)?;
+            let address =
+                Address::from_str(&params.address)?.require_network(coordinator.network())?;
+            let amount = Amount::from_sat(params.amount);
+            let feerate = params.feerate.unwrap_or(DEFAULT_FEERATE);
+            let feerate = FeeRate::from_sat_per_kwu((feerate * 250.0).round() as u64);
+            let psbt = coordinator
+                .wallet
+                .create_psbt(&address, amount, feerate, params.sweep)?;
+            Response::ok(PsbtInfo::from(&psbt))
+        }
+        // Inspect PSBT
+        ("POST", "/psbt/inspect") => {
+            let params: InspectPsbt = serde_json::from_slice(body)?;
+            let psbt = Psbt::from_str(&params.psbt)?;
+            Response::ok(PsbtDetail::new(coordinator.wallet(), &psbt))
+        }
+        // Combine PSBTs
+        ("POST", "/psbt/combine") => {
+            let params: CombinePsbt = serde_json::from_slice(body)?;
+            let mut psbts = params.psbts.iter().map(|s| Psbt::from_str(s));
+            let Some(psbt) = psbts.next() else {
+                bail!("no psbts provided");
+            };
+            let mut psbt = psbt?;
+            for other in psbts {
+                psbt.combine(other?)?;
+            }
+            Response::ok(PsbtInfo::from(&psbt))
+        }
+        // Accounts
+        ("GET", "/accounts") => {
+            let db = super::db::open()?;
+            let accounts = super::db::list_accounts(&db)?;
+            Response::ok(accounts.iter().map(AccountInfo::from).collect::<Vec<_>>())
+        }
+        ("POST", "/accounts") => {
+            let params: NewAccount = serde_json::from_slice(body)?;
+            let db = super::db::open()?;
+            let transport = params.transport.as_deref().unwrap_or("note").parse()?;
+            let id = super::db::insert_account(
+                &db,
+                &params.network,
+                &params.nick,
+                &params.descriptor,
+                transport,
+            )?;
+            Response::ok(json!({ "id": id }))
+        }
+        ("POST", "/friends") => {
+            let params: NewFriend = serde_json::from_slice(body)?;
+            let db = super::db::open()?;
+            let ct = super::db::insert_friend(
+                &db,
+                params.account_id,
+                params.quorum_id,
+                &params.npub,
+                params.alias.as_deref(),
+            )?;
+            Response::ok(json!({ "inserted": ct }))
+        }
+        // Fetch calls
+        #[cfg(feature = "nostr-sdk")]
+        ("GET", "/calls") => {
+            let entries = super::fetch::fetch(coordinator, false).await?;
+            let entries: Vec<_> = entries.iter().map(super::output::ChatInfo::from).collect();
+            Response::ok(entries)
+        }
+        // Send a call
+        #[cfg(feature = "nostr-sdk")]
+        ("POST", "/calls") => {
+            let params: NewCall = serde_json::from_slice(body)?;
+            let opt = crate::cli::CallOpt {
+                recipient: crate::cli::Recipient {
+                    id: params.id,
+                    alias: params.alias,
+                    all: params.all,
+                },
+                note: params.note,
+                psbt: params.psbt,
+                signed: params.signed,
+                memo: params.memo,
+                ack: params.ack,
+                nack: params.nack,
+                reason: params.reason,
+                ref_event: params.ref_event,
+                reply_to: params.reply_to,
+                ttl: params.ttl,
+                dryrun: false,
+            };
+            let expires_at = super::call::expires_at(&opt)?;
+            let calls = super::call::new_call(coordinator, &opt, expires_at).await?;
+            let outcome = super::call::send(coordinator, &calls, expires_at).await?;
+            // the parts sent are reported along with those that failed
+            let status = if outcome.failed.is_empty() { 200 } else { 502 };
+            Ok(Response {
+                status,
+                body: super::call::sent_json(&outcome),
+            })
+        }
+        _ => Ok(Response::error(404, "not found")),
+    }
+}
+
+/// Sync the wallet to the chain tip on a thread of its own, as the sync blocks. Other requests
+/// for the coordinator wait until it's done.
+async fn sync(coordinator: Arc<Mutex<Coordinator>>) -> Result<Response> {
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        let mut coordinator = coordinator.blocking_lock();
+        let res = coordinator
+            .sync(None, |_| {})
+            .map_err(anyhow::Error::from)
+            .and_then(|events| {
+                coordinator.persist()?;
+                Ok((coordinator.account_id, coordinator.wallet().tip(), events))
+            });
+        let _ = tx.send(res);
+    });
+    let (account_id, tip, events) = rx.await??;
+
+    super::hook::on_wallet_events(account_id, &events).await;
+    Response::ok(SyncInfo {
+        height: tip.height(),
+        hash: tip.hash().to_string(),
+        events: events.iter().map(EventInfo::from).collect(),
+    })
+}
+
+/// Compare two byte strings in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
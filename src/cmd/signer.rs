@@ -0,0 +1,54 @@
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+#[cfg(feature = "bunker")]
+use std::time::Duration;
+
+use nostr_sdk::prelude::*;
+
+use super::{bail, Context, Result};
+
+/// How long to wait for a remote signer to respond.
+#[cfg(feature = "bunker")]
+const BUNKER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Load the nostr signer of the account, in order of preference:
+///
+/// - `NOSTR_BUNKER`: NIP-46 bunker URI of a remote signer (`bunker` feature)
+/// - `NOSTR_KEYFILE`: path to a NIP-49 encrypted secret key, unlocked with a passphrase read
+///   from the terminal
+/// - The key of the account in the keystore, see `loon key`
+/// - `NOSTR_NSEC`: raw secret key, discouraged
+pub fn load(account_id: u32) -> Result<Arc<dyn NostrSigner>> {
+    #[cfg(feature = "bunker")]
+    if let Ok(uri) = env::var("NOSTR_BUNKER") {
+        let uri = nip46::NostrConnectURI::parse(&uri).context("invalid NOSTR_BUNKER")?;
+        // the bunker authorizes these app keys, so reuse them
+        let app_keys = super::key::bunker_keys(account_id)?;
+        let signer = nostr_connect::client::NostrConnect::new(uri, app_keys, BUNKER_TIMEOUT, None)?;
+        return Ok(Arc::new(signer));
+    }
+    if let Ok(path) = env::var("NOSTR_KEYFILE") {
+        return Ok(Arc::new(read_keyfile(Path::new(&path))?));
+    }
+    let path = super::key::keyfile_path(account_id);
+    if path.exists() {
+        return Ok(Arc::new(read_keyfile(&path)?));
+    }
+    if let Ok(nsec) = env::var("NOSTR_NSEC") {
+        return Ok(Arc::new(Keys::parse(&nsec)?));
+    }
+
+    bail!("no nostr key for account id {account_id}, see `loon key generate` or `loon key import`")
+}
+
+/// Read the NIP-49 encrypted secret key (`ncryptsec`) at `path`, prompting for the passphrase.
+pub fn read_keyfile(path: &Path) -> Result<Keys> {
+    let ncryptsec = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let encrypted = EncryptedSecretKey::from_bech32(ncryptsec.trim()).context("invalid keyfile")?;
+    let passphrase = rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))?;
+    let secret_key = encrypted.decrypt(&passphrase).context("wrong passphrase")?;
+
+    Ok(Keys::new(secret_key))
+}
@@ -0,0 +1,340 @@
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, List, ListState, Paragraph, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+
+use loon::{CallTy, ChatEntry, Coordinator, Pid};
+
+use super::output::{self, PsbtDetail, TxInfo};
+use super::Result;
+use crate::cli::{CallOpt, Recipient};
+
+/// How long to wait for input before redrawing.
+const TICK: Duration = Duration::from_millis(250);
+
+/// Key bindings help.
+const HELP: &str = "←/→ tab  ↑/↓ select  r refresh  a ack  n nack  q quit";
+
+/// Tabs of the TUI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tab {
+    Balance,
+    History,
+    Inbox,
+    Psbts,
+}
+
+impl Tab {
+    const ALL: [Self; 4] = [Self::Balance, Self::History, Self::Inbox, Self::Psbts];
+
+    fn title(self) -> &'static str {
+        match self {
+            Self::Balance => "Balance",
+            Self::History => "History",
+            Self::Inbox => "Inbox",
+            Self::Psbts => "Pending PSBTs",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|&tab| tab == self).expect("must be a tab")
+    }
+
+    fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+
+    fn previous(self) -> Self {
+        Self::ALL[(self.index() + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+/// A PSBT received from a quorum participant.
+#[derive(Debug)]
+struct PendingPsbt {
+    /// Requester participant id
+    pid: Pid,
+    /// Requester alias
+    alias: String,
+    /// Id of the request event
+    event_id: String,
+    memo: Option<String>,
+    detail: PsbtDetail,
+}
+
+/// TUI state.
+#[derive(Debug)]
+struct App {
+    tab: Tab,
+    balance: String,
+    txs: Vec<TxInfo>,
+    inbox: Vec<ChatEntry>,
+    psbts: Vec<PendingPsbt>,
+    state: ListState,
+    status: String,
+    quit: bool,
+}
+
+/// Run the terminal UI until the user quits.
+///
+/// Diagnostics are shown in the status line rather than printed over the UI.
+pub async fn run(coordinator: &Coordinator) -> Result<()> {
+    output::collect_diagnostics();
+    let mut app = App {
+        tab: Tab::Balance,
+        balance: String::new(),
+        txs: vec![],
+        inbox: vec![],
+        psbts: vec![],
+        state: ListState::default(),
+        status: String::new(),
+        quit: false,
+    };
+    app.refresh(coordinator).await?;
+    app.show_diagnostics();
+
+    let mut terminal = ratatui::init();
+    let res = app.run(coordinator, &mut terminal).await;
+    ratatui::restore();
+
+    res
+}
+
+impl App {
+    /// Handle input and redraw until the user quits.
+    async fn run(
+        &mut self,
+        coordinator: &Coordinator,
+        terminal: &mut DefaultTerminal,
+    ) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(TICK)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+                KeyCode::Right | KeyCode::Tab => self.select_tab(self.tab.next()),
+                KeyCode::Left | KeyCode::BackTab => self.select_tab(self.tab.previous()),
+                KeyCode::Down | KeyCode::Char('j') => self.state.select_next(),
+                KeyCode::Up | KeyCode::Char('k') => self.state.select_previous(),
+                KeyCode::Char('r') => {
+                    self.status = "Refreshing..".to_string();
+                    terminal.draw(|frame| self.draw(frame))?;
+                    if let Err(e) = self.refresh(coordinator).await {
+                        self.status = format!("Refresh failed: {e}");
+                    }
+                }
+                KeyCode::Char('a') if self.tab == Tab::Psbts => {
+                    self.respond(coordinator, true).await
+                }
+                KeyCode::Char('n') if self.tab == Tab::Psbts => {
+                    self.respond(coordinator, false).await
+                }
+                _ => {}
+            }
+            self.show_diagnostics();
+        }
+
+        Ok(())
+    }
+
+    /// Reload the wallet state, fetch the latest calls and reload the inbox.
+    async fn refresh(&mut self, coordinator: &Coordinator) -> Result<()> {
+        self.balance = super::wallet::format_balance(coordinator)?;
+        self.txs = TxInfo::list(coordinator.wallet());
+        super::fetch::fetch(coordinator, false).await?;
+        let db = super::db::open()?;
+        let entries: Vec<_> = super::inbox::list(&db, coordinator.account_id)?
+            .into_iter()
+            .filter(|entry| !entry.archived)
+            .collect();
+        self.inbox = entries
+            .iter()
+            .map(|entry| super::inbox::chat_entry(coordinator, entry))
+            .collect::<Result<_>>()?;
+        // requests we haven't responded to
+        self.psbts = self
+            .inbox
+            .iter()
+            .zip(&entries)
+            .filter(|(_, entry)| entry.response.is_none())
+            .filter_map(|(entry, _)| match &entry.call {
+                CallTy::PsbtRequest { psbt, memo } => Some(PendingPsbt {
+                    pid: entry.pid,
+                    alias: entry.alias.clone(),
+                    event_id: entry.event_id.clone(),
+                    memo: memo.clone(),
+                    detail: PsbtDetail::new(coordinator.wallet(), psbt),
+                }),
+                _ => None,
+            })
+            .collect();
+        self.status = format!("{} notes, {} pending PSBTs", self.inbox.len(), self.psbts.len());
+
+        Ok(())
+    }
+
+    /// Ack or nack the selected PSBT, sending the call to its requester. Once sent, the response
+    /// is stored with the request, which is no longer pending.
+    async fn respond(&mut self, coordinator: &Coordinator, ack: bool) {
+        let Some(index) = self.state.selected() else {
+            return;
+        };
+        let index = index.min(self.psbts.len().saturating_sub(1));
+        let Some(pending) = self.psbts.get(index) else {
+            return;
+        };
+
+        let opt = CallOpt {
+            recipient: Recipient {
+                id: Some(pending.pid.as_u32()),
+                alias: None,
+                all: false,
+            },
+            note: None,
+            psbt: None,
+            signed: false,
+            memo: None,
+            ack,
+            nack: !ack,
+            reason: None,
+            ref_event: None,
+            reply_to: Some(pending.event_id.clone()),
+            ttl: None,
+            dryrun: false,
+        };
+        let response = if ack { "ack" } else { "nack" };
+        let res: Result<_> = async {
+            let calls = super::call::new_call(coordinator, &opt, None).await?;
+            let outcome = super::call::send(coordinator, &calls, None).await?;
+            if outcome.failed.is_empty() {
+                let db = super::db::open()?;
+                let account_id = coordinator.account_id;
+                super::inbox::set_response(&db, account_id, &pending.event_id, response)?;
+            }
+            Ok(outcome)
+        }
+        .await;
+
+        let answered = matches!(&res, Ok(outcome) if outcome.failed.is_empty());
+        let reply = if ack { "Ack" } else { "Nack" };
+        self.status = match res {
+            Ok(outcome) if !outcome.failed.is_empty() => {
+                let e = &outcome.failed[0].error;
+                format!("{reply} failed: {e}")
+            }
+            Ok(outcome) => {
+                let failed = outcome.sent.iter().map(|s| s.failed.len()).sum::<usize>();
+                let hints = match &outcome.hints_error {
+                    Some(e) => format!(", our relays only: {e}"),
+                    None => String::new(),
+                };
+                format!(
+                    "{reply} sent to {} for {} ({failed} relays failed{hints})",
+                    pending.alias, pending.detail.txid
+                )
+            }
+            Err(e) => format!("{reply} failed: {e}"),
+        };
+        if answered {
+            self.psbts.remove(index);
+        }
+    }
+
+    /// Append the diagnostics reported since last shown to the status, the latest in full.
+    fn show_diagnostics(&mut self) {
+        let diagnostics = output::take_diagnostics();
+        if let Some(latest) = diagnostics.last() {
+            self.status = format!("{} ({} warnings: {latest})", self.status, diagnostics.len());
+        }
+    }
+
+    fn select_tab(&mut self, tab: Tab) {
+        self.tab = tab;
+        self.state = ListState::default().with_selected(Some(0));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [tabs_area, main_area, status_area] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)])
+                .areas(frame.area());
+
+        let tabs = Tabs::new(Tab::ALL.iter().map(|tab| tab.title()))
+            .select(self.tab.index())
+            .block(Block::bordered().title("Loon"))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_widget(tabs, tabs_area);
+
+        let block = Block::bordered().title(self.tab.title());
+        match self.tab {
+            Tab::Balance => {
+                frame.render_widget(Paragraph::new(self.balance.as_str()).block(block), main_area)
+            }
+            Tab::History => {
+                let items = self
+                    .txs
+                    .iter()
+                    .map(|tx| {
+                        let height = match tx.confirmation_height {
+                            Some(height) => height.to_string(),
+                            None => "unconfirmed".to_string(),
+                        };
+                        format!("{} +{} -{} {}", tx.txid, tx.received, tx.sent, height)
+                    })
+                    .collect();
+                self.draw_list(frame, items, block, main_area);
+            }
+            Tab::Inbox => {
+                let items = self
+                    .inbox
+                    .iter()
+                    .map(|entry| format!("{}: {}", entry.alias, entry.call))
+                    .collect();
+                self.draw_list(frame, items, block, main_area);
+            }
+            Tab::Psbts => {
+                let items = self
+                    .psbts
+                    .iter()
+                    .map(|pending| {
+                        let detail = &pending.detail;
+                        let sent: u64 = detail
+                            .outputs
+                            .iter()
+                            .filter(|txout| !txout.is_mine)
+                            .map(|txout| txout.value)
+                            .sum();
+                        let fee = detail.fee.map_or("?".to_string(), |fee| fee.to_string());
+                        let memo = pending.memo.as_deref().unwrap_or_default();
+                        format!(
+                            "{}: {} send {} fee {} {}",
+                            pending.alias, detail.txid, sent, fee, memo
+                        )
+                    })
+                    .collect();
+                self.draw_list(frame, items, block, main_area);
+            }
+        }
+
+        frame.render_widget(Paragraph::new(format!("{} | {HELP}", self.status)), status_area);
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, items: Vec<String>, block: Block, area: Rect) {
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, area, &mut self.state);
+    }
+}
@@ -1,21 +1,16 @@
-use anyhow::Context;
-use bdk_chain::bdk_core;
 use bdk_chain::bitcoin;
-use bdk_chain::SpkIterator;
-use bdk_core::BlockId;
 use bitcoin::{address::FromScriptError, Address, Amount, FeeRate};
-use filter_iter::FilterIter;
 
-use loon::{simplerpc, Coordinator, Keychain, Update};
+use loon::{Coordinator, Keychain, SyncProgress};
 
+use super::output::{
+    self, AddressInfo, BalanceInfo, EventInfo, PsbtInfo, SyncInfo, TxInfo, TxOutInfo,
+};
 use super::Result;
 use crate::cli::{AddressSubCmd, TxSubCmd, WalletSubCmd};
 
-/// Minimum count of script pubkeys to scan with if none are revealed.
-const SPK_CT: u32 = 20;
-
 // Perform wallet operations.
-pub async fn execute(coor: &mut Coordinator, subcmd: WalletSubCmd) -> Result<()> {
+pub async fn execute(coor: &mut Coordinator, subcmd: WalletSubCmd, json: bool) -> Result<()> {
     let network = coor.network();
 
     match subcmd {
@@ -26,7 +21,7 @@ pub async fn execute(coor: &mut Coordinator, subcmd: WalletSubCmd) -> Result<()>
                     let (keychain, index) = indexed;
                     coor.persist()?;
 
-                    println!("({} {}) {}", keychain, index, addr);
+                    display_address(keychain, index, &addr, json)?;
                 }
             }
             AddressSubCmd::Next => {
@@ -34,14 +29,14 @@ pub async fn execute(coor: &mut Coordinator, subcmd: WalletSubCmd) -> Result<()>
                     let (keychain, index) = indexed;
                     coor.persist()?;
 
-                    println!("({} {}) {}", keychain, index, addr);
+                    display_address(keychain, index, &addr, json)?;
                 }
             }
             AddressSubCmd::Peek { index, keychain } => {
                 if let Some((indexed, addr)) = coor.wallet.peek_address(keychain.into(), index) {
                     let (keychain, index) = indexed;
 
-                    println!("({} {}) {}", keychain, index, addr);
+                    display_address(keychain, index, &addr, json)?;
                 }
             }
             AddressSubCmd::List { keychain } => {
@@ -58,16 +53,34 @@ pub async fn execute(coor: &mut Coordinator, subcmd: WalletSubCmd) -> Result<()>
                     })
                     .collect::<Result<Vec<_>, _>>()?;
 
-                for (index, addr, is_used) in addrs {
-                    println!("({} {}) {} used:{}", keychain, index, addr, is_used);
+                if json {
+                    let addrs: Vec<_> = addrs
+                        .into_iter()
+                        .map(|(index, addr, is_used)| AddressInfo {
+                            used: Some(is_used),
+                            ..AddressInfo::new(keychain, index, &addr)
+                        })
+                        .collect();
+                    output::print_json(&addrs)?;
+                } else {
+                    for (index, addr, is_used) in addrs {
+                        println!("({} {}) {} used:{}", keychain, index, addr, is_used);
+                    }
                 }
             }
         },
         // Balance
-        WalletSubCmd::Balance => display_balance(coor)?,
+        WalletSubCmd::Balance => {
+            if json {
+                output::print_json(&BalanceInfo::new(coor.wallet())?)?;
+            } else {
+                display_balance(coor)?;
+            }
+        }
         // Tx
         WalletSubCmd::Tx(cmd) => match cmd {
             // List transactions by txid
+            TxSubCmd::List if json => output::print_json(&TxInfo::list(coor.wallet()))?,
             TxSubCmd::List => {
                 for canon_tx in coor.wallet().transactions() {
                     // TODO: maybe display more tx details (sent, received, etc).
@@ -75,6 +88,15 @@ pub async fn execute(coor: &mut Coordinator, subcmd: WalletSubCmd) -> Result<()>
                 }
             }
             // List tx outputs.
+            TxSubCmd::Out { unspent } if json => {
+                let txouts = coor
+                    .wallet()
+                    .list_indexed_txouts()
+                    .filter(|(_, txo)| !unspent || txo.spent_by.is_none())
+                    .map(|(indexed, txo)| TxOutInfo::new(coor.wallet(), indexed, &txo))
+                    .collect::<Result<Vec<_>>>()?;
+                output::print_json(&txouts)?;
+            }
             TxSubCmd::Out { unspent } => {
                 for ((keychain, index), txo) in coor.wallet.list_indexed_txouts() {
                     let is_spent = txo.spent_by.is_some();
@@ -105,8 +127,16 @@ pub async fn execute(coor: &mut Coordinator, subcmd: WalletSubCmd) -> Result<()>
 
                 let psbt = coor.wallet.create_psbt(&address, amount, feerate, sweep)?;
 
-                dbg!(&psbt);
-                println!("{}", psbt);
+                let info = PsbtInfo::from(&psbt);
+                if json {
+                    output::print_json(&info)?;
+                } else {
+                    println!("Txid: {}", info.txid);
+                    if let Some(fee) = info.fee {
+                        println!("Fee: {}", Amount::from_sat(fee));
+                    }
+                    println!("{}", info.psbt);
+                }
             }
         },
         // Display the person alias for the current user.
@@ -119,66 +149,46 @@ pub async fn execute(coor: &mut Coordinator, subcmd: WalletSubCmd) -> Result<()>
                 .find(|(_pid, p)| p.pk == my_pk)
                 .expect("must find participant");
 
-            println!("{}: {}", pid, p.alias.clone().unwrap_or("None".to_string()));
+            if json {
+                output::print_json(&serde_json::json!({
+                    "pid": pid.as_u32(),
+                    "alias": p.alias,
+                }))?;
+            } else {
+                println!("{}: {}", pid, p.alias.clone().unwrap_or("None".to_string()));
+            }
         }
         // Sync to chain tip
         WalletSubCmd::Sync { start } => {
-            if let Some(height) = start {
-                // We want to insert a block if we haven't reached the start height to prevent
-                // scanning the entire chain.
-                if height > coor.wallet().tip().height() {
-                    let hash = coor.rpc_client().get_block_hash(height as _)?;
-                    let block = BlockId { height, hash };
-                    let wallet = coor.wallet_mut();
-                    wallet.insert_checkpoint(block).map_err(|e| anyhow::anyhow!("{e}"))?;
+            let events = coor.sync(start, |progress| match progress {
+                // Progress goes to stderr so as not to interfere with JSON output.
+                SyncProgress::Matched(height) if json => eprintln!("Matched block {height}"),
+                SyncProgress::Matched(height) => println!("Matched block {height}"),
+                SyncProgress::Scanned(height) if height % 100 == 0 => {
+                    if json {
+                        eprintln!("Scanning.. {height}");
+                    } else {
+                        println!("Scanning.. {height}");
+                    }
                 }
-            }
+                SyncProgress::Scanned(_) => {}
+            })?;
 
-            let mut spks = vec![];
-            for (keychain, desc) in coor.wallet().index.keychains() {
-                let last_reveal = coor
-                    .wallet()
-                    .index
-                    .last_revealed_index(keychain)
-                    .unwrap_or_default()
-                    .max(SPK_CT);
-                spks.extend(SpkIterator::new_with_range(desc, 0..=last_reveal).map(|(_, s)| s));
-            }
+            coor.persist()?;
+            super::hook::on_wallet_events(coor.account_id, &events).await;
 
-            let mut cp = coor.wallet().tip();
-            let start_height = cp.height();
-            let rpc_client = get_rpc_client(network)?;
-            let filter_iter = FilterIter::new(&rpc_client, cp.clone(), spks);
-            let mut new_tip = cp.block_id();
-
-            for result in filter_iter {
-                let event = result?;
-                let block_id = event.cp.block_id();
-                let height = block_id.height;
-                // Add matching blocks to tip (including those that may have been reorganized).
-                if height <= start_height || event.is_match() {
-                    cp = cp.insert(block_id);
-                }
-                // Apply matching blocks
-                if let Some(ref block) = event.block {
-                    coor.wallet_mut().apply_block_relevant(block, height);
-                    println!("Matched block {height}");
-                } else if height % 100 == 0 {
-                    println!("Scanning.. {height}");
-                }
-                new_tip = block_id;
+            if json {
+                let tip = coor.wallet().tip();
+                return output::print_json(&SyncInfo {
+                    height: tip.height(),
+                    hash: tip.hash().to_string(),
+                    events: events.iter().map(EventInfo::from).collect(),
+                });
             }
 
-            // Also include the new tip.
-            cp = cp.insert(new_tip);
-
-            // Apply chain update.
-            coor.wallet.apply_update(Update {
-                cp: Some(cp),
-                ..Default::default()
-            })?;
-
-            coor.persist()?;
+            for event in events {
+                println!("{event}");
+            }
 
             println!("Local tip: {}\n", coor.wallet().tip().height());
             display_balance(coor)?;
@@ -190,19 +200,39 @@ pub async fn execute(coor: &mut Coordinator, subcmd: WalletSubCmd) -> Result<()>
     Ok(())
 }
 
+fn display_address(keychain: Keychain, index: u32, addr: &Address, json: bool) -> Result<()> {
+    if json {
+        output::print_json(&AddressInfo::new(keychain, index, addr))
+    } else {
+        println!("({} {}) {}", keychain, index, addr);
+        Ok(())
+    }
+}
+
 fn display_balance(coor: &Coordinator) -> Result<()> {
+    print!("{}", format_balance(coor)?);
+
+    Ok(())
+}
+
+/// Format the wallet unspent outputs and balance for display.
+pub fn format_balance(coor: &Coordinator) -> Result<String> {
+    use std::fmt::Write;
+
     let network = coor.network();
     let wallet = coor.wallet();
+    let mut s = String::new();
 
     let unspent: Vec<_> = wallet.list_unspent().collect();
 
     // List unspent.
     if !unspent.is_empty() {
-        println!("Unspent");
+        writeln!(s, "Unspent")?;
         for (indexed, txo) in unspent {
             let (keychain, index) = indexed;
             let txout = txo.txout;
-            println!(
+            writeln!(
+                s,
                 // (k, index) | address | value | outpoint
                 "({} {}) | {} | {} | {}",
                 keychain,
@@ -210,31 +240,18 @@ fn display_balance(coor: &Coordinator) -> Result<()> {
                 Address::from_script(&txout.script_pubkey, network)?,
                 txout.value,
                 txo.outpoint,
-            );
+            )?;
         }
     }
 
     // Display Balance.
-    println!("\n{:#?}", wallet.balance());
-
-    Ok(())
-}
+    let balance = wallet.balance();
+    writeln!(s, "\nBalance")?;
+    writeln!(s, "Confirmed: {}", balance.confirmed)?;
+    writeln!(s, "Trusted pending: {}", balance.trusted_pending)?;
+    writeln!(s, "Untrusted pending: {}", balance.untrusted_pending)?;
+    writeln!(s, "Immature: {}", balance.immature)?;
+    writeln!(s, "Total: {}", balance.total())?;
 
-/// Initialize a new RPC client. This is here to avoid holding a reference to the
-/// Coordinator's client while we're mutating the wallet.
-fn get_rpc_client(network: bitcoin::Network) -> anyhow::Result<simplerpc::Client> {
-    let rpc_port = match network {
-        bitcoin::Network::Signet => 38332,
-        bitcoin::Network::Bitcoin => 8332,
-        _ => panic!("unsupported Network"),
-    };
-    let url = format!("http://127.0.0.1:{rpc_port}");
-    let cookie_file = std::env::var("RPC_COOKIE").context("must set RPC_COOKIE")?;
-    let cookie = std::fs::read_to_string(cookie_file)?;
-    let simple_http = simplerpc::jsonrpc::simple_http::Builder::new()
-        .url(&url)?
-        .cookie_auth(cookie)
-        .build();
-
-    Ok(simplerpc::Client::with_transport(simple_http))
+    Ok(s)
 }
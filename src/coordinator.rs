@@ -1,18 +1,36 @@
 use std::fmt;
 use std::sync::{Arc, Mutex};
 
-use bdk_chain::bitcoin;
+use bdk_chain::{bdk_core::BlockId, bitcoin, SpkIterator};
+use bitcoin::hex::DisplayHex;
+use filter_iter::FilterIter;
 
 #[cfg(feature = "nostr-sdk")]
 use nostr_sdk::prelude::{self as nostr, *};
 
 #[allow(unused_imports)]
 use crate::Error;
-use crate::{rusqlite, simplerpc, BdkWallet as Wallet};
+use crate::{rusqlite, simplerpc, BdkWallet as Wallet, Update, WalletEvent};
+
+mod chunk;
+#[cfg(feature = "nostr-sdk")]
+mod exchange;
+#[cfg(feature = "nostr-sdk")]
+mod relays;
+pub use chunk::*;
+#[cfg(feature = "nostr-sdk")]
+pub use exchange::*;
+#[cfg(feature = "nostr-sdk")]
+pub use relays::*;
+
+/// Minimum count of script pubkeys to scan with if none are revealed.
+const SPK_CT: u32 = 20;
 
 /// Coordinator
 #[derive(Debug)]
 pub struct Coordinator {
+    /// loon account id
+    pub account_id: u32,
     /// quorum fingerprint
     pub fingerprint: String,
     /// Bdk wallet
@@ -25,6 +43,9 @@ pub struct Coordinator {
     // Nostr client
     #[cfg(feature = "nostr-sdk")]
     pub client: Arc<nostr::Client>,
+    /// How calls are sent
+    #[cfg(feature = "nostr-sdk")]
+    pub transport: Transport,
     // RPC client
     pub rpc_client: simplerpc::Client,
 }
@@ -63,6 +84,29 @@ impl Coordinator {
         self.client.clone()
     }
 
+    /// Get a [`Messenger`](crate::Messenger) over the nostr client using the transport of the
+    /// account, publishing to the relays each participant reads from.
+    ///
+    /// If the relay lists can't be fetched, only the account's own relays are used, and the
+    /// error is returned next to the messenger.
+    #[cfg(feature = "nostr-sdk")]
+    pub async fn messenger(&self) -> Result<(crate::NostrMessenger, Option<Error>), Error> {
+        let (hints, hints_error) = match self.relay_hints().await {
+            Ok(hints) => (hints, None),
+            Err(e) => (Default::default(), Some(e)),
+        };
+        let read_relays = hints
+            .into_iter()
+            .filter_map(|(pid, hints)| {
+                let p = self.participants.get(&pid)?;
+                Some((p.pk.to_hex(), hints.read))
+            })
+            .collect();
+        let messenger = crate::NostrMessenger::new(self.client(), self.transport).await?;
+
+        Ok((messenger.with_read_relays(read_relays), hints_error))
+    }
+
     /// Get a reference to the blockchain RPC client.
     pub fn rpc_client(&self) -> &simplerpc::Client {
         &self.rpc_client
@@ -83,12 +127,113 @@ impl Coordinator {
     }
 
     /// Creates a new `Call` to `recipient` with the given `payload`.
-    pub fn call_new_with_recipient_and_payload(&self, recipient: Pid, payload: &str) -> Call {
-        let mut call = Call::new(crate::HRP);
-        call.push(self.quorum_fingerprint())
-            .push(&recipient.to_string())
-            .build(payload);
-        call
+    ///
+    /// Errors if the `recipient` id can't be encoded.
+    pub fn call_new_with_recipient_and_payload(
+        &self,
+        recipient: Pid,
+        payload: &str,
+    ) -> Result<Call, CallError> {
+        Call::encode(self.quorum_fingerprint(), recipient, payload)
+    }
+
+    /// Sync the wallet to the tip of the chain source by scanning compact block filters.
+    ///
+    /// If `start` is above the local tip, the scan begins from that height rather than from the
+    /// last known checkpoint. `inspect` is called with the progress of each block scanned.
+    ///
+    /// Returns the [`WalletEvent`]s describing the change to the wallet.
+    /// **You must persist the staged changes**.
+    pub fn sync(
+        &mut self,
+        start: Option<u32>,
+        mut inspect: impl FnMut(SyncProgress),
+    ) -> Result<Vec<WalletEvent>, Error> {
+        let rpc_client = &self.rpc_client;
+        let wallet = &mut self.wallet;
+        let snapshot = wallet.snapshot();
+
+        if let Some(height) = start {
+            // We want to insert a block if we haven't reached the start height to prevent
+            // scanning the entire chain.
+            if height > wallet.tip().height() {
+                let hash = rpc_client
+                    .get_block_hash(height as _)
+                    .map_err(|e| Error::Chain(e.to_string()))?;
+                let block = BlockId { height, hash };
+                wallet.insert_checkpoint(block).map_err(Error::Coordinator)?;
+            }
+        }
+
+        let mut spks = vec![];
+        for (keychain, desc) in wallet.index.keychains() {
+            let last_reveal = wallet
+                .index
+                .last_revealed_index(keychain)
+                .unwrap_or_default()
+                .max(SPK_CT);
+            spks.extend(SpkIterator::new_with_range(desc, 0..=last_reveal).map(|(_, s)| s));
+        }
+
+        let mut cp = wallet.tip();
+        let start_height = cp.height();
+        let filter_iter = FilterIter::new(rpc_client, cp.clone(), spks);
+        let mut new_tip = cp.block_id();
+
+        for result in filter_iter {
+            let event = result.map_err(|e| Error::Chain(e.to_string()))?;
+            let block_id = event.cp.block_id();
+            let height = block_id.height;
+            // Add matching blocks to tip (including those that may have been reorganized).
+            if height <= start_height || event.is_match() {
+                cp = cp.insert(block_id);
+            }
+            // Apply matching blocks
+            if let Some(ref block) = event.block {
+                wallet.apply_block_relevant(block, height);
+                inspect(SyncProgress::Matched(height));
+            } else {
+                inspect(SyncProgress::Scanned(height));
+            }
+            new_tip = block_id;
+        }
+
+        // Also include the new tip.
+        cp = cp.insert(new_tip);
+
+        // Apply chain update.
+        wallet
+            .apply_update(Update {
+                cp: Some(cp),
+                ..Default::default()
+            })
+            .map_err(|e| Error::Chain(e.to_string()))?;
+
+        Ok(wallet.events_since(&snapshot))
+    }
+
+    /// Handle a ZMQ `notification` by syncing to the new tip when a block is connected, or
+    /// inserting a relevant transaction as unconfirmed.
+    ///
+    /// Returns the [`WalletEvent`]s describing the change to the wallet.
+    /// **You must persist the staged changes**.
+    #[cfg(feature = "zmq")]
+    pub fn handle_notification(
+        &mut self,
+        notification: crate::Notification,
+    ) -> Result<Vec<WalletEvent>, Error> {
+        match notification {
+            crate::Notification::Block(_) => self.sync(None, |_| {}),
+            crate::Notification::Tx(tx) => {
+                let seen_at = std::time::UNIX_EPOCH
+                    .elapsed()
+                    .expect("system time should be after the epoch")
+                    .as_secs();
+                let snapshot = self.wallet.snapshot();
+                self.wallet.apply_unconfirmed_tx(tx, seen_at);
+                Ok(self.wallet.events_since(&snapshot))
+            }
+        }
     }
 
     /// Persist the changes that have been staged by the onchain wallet.
@@ -100,6 +245,47 @@ impl Coordinator {
     }
 }
 
+/// How calls are sent to quorum participants.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transport {
+    /// Public text note on the sender's feed
+    #[default]
+    Note,
+    /// NIP-17 private direct message, sealed and gift wrapped according to NIP-59. This hides
+    /// the sender, recipient and quorum fingerprint from observers.
+    GiftWrap,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Note => write!(f, "note"),
+            Self::GiftWrap => write!(f, "gift-wrap"),
+        }
+    }
+}
+
+impl std::str::FromStr for Transport {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "note" => Ok(Self::Note),
+            "gift-wrap" => Ok(Self::GiftWrap),
+            _ => Err(Error::Coordinator(format!("unknown transport {s}"))),
+        }
+    }
+}
+
+/// Progress of a [`Coordinator::sync`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncProgress {
+    /// The block at height matched the wallet's script pubkeys and was applied
+    Matched(u32),
+    /// The block at height was scanned without a match
+    Scanned(u32),
+}
+
 /// A participant in a quorum.
 #[derive(Debug)]
 #[cfg(feature = "nostr-sdk")]
@@ -128,10 +314,18 @@ impl From<crate::Friend> for Participant {
 pub struct Pid(u32);
 
 impl Pid {
+    /// The largest Pid that can be addressed by a [`Call`].
+    pub const MAX: u32 = u16::MAX as u32;
+
     /// Get the Pid as u32.
     pub fn as_u32(self) -> u32 {
         self.0
     }
+
+    /// Get the Pid as u16, if it can be addressed by a [`Call`].
+    pub fn to_u16(self) -> Option<u16> {
+        u16::try_from(self.0).ok()
+    }
 }
 
 impl From<u32> for Pid {
@@ -142,8 +336,6 @@ impl From<u32> for Pid {
 
 impl fmt::Display for Pid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // This allows us to parse incoming `Call`s, because we can rely
-        // on a length of 2 for the pid encoding.
         let u = self.0;
         let s = if u <= 9 {
             format!("0{u}")
@@ -154,41 +346,179 @@ impl fmt::Display for Pid {
     }
 }
 
-/// Types of calls.
-#[derive(Debug)]
+/// Types of calls, carried by a [`CallPayload`].
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum CallTy {
-    /// Nack
-    Nack,
-    /// Ack
-    Ack,
-    /// Note
-    Note(String),
+    /// Request to sign a PSBT
+    PsbtRequest {
+        #[serde(with = "psbt_base64")]
+        psbt: bitcoin::Psbt,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        memo: Option<String>,
+    },
+    /// A signed PSBT
+    PsbtSigned {
+        #[serde(with = "psbt_base64")]
+        psbt: bitcoin::Psbt,
+    },
+    /// Ack, optionally referencing the event being acknowledged
+    Ack {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ref_event: Option<String>,
+    },
+    /// Nack, optionally referencing the event being rejected
+    Nack {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ref_event: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+    /// Text note
+    Note { text: String },
 }
 
 impl CallTy {
-    /// The numeric id of self.
-    pub fn id(&self) -> u8 {
+    /// Name of the call type, as serialized.
+    pub fn name(&self) -> &'static str {
         match self {
-            Self::Nack => 0,
-            Self::Ack => 1,
-            Self::Note(_) => 2,
+            Self::PsbtRequest { .. } => "psbt_request",
+            Self::PsbtSigned { .. } => "psbt_signed",
+            Self::Ack { .. } => "ack",
+            Self::Nack { .. } => "nack",
+            Self::Note { .. } => "note",
         }
     }
 }
 
-impl AsRef<str> for CallTy {
-    fn as_ref(&self) -> &str {
+impl fmt::Display for CallTy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Nack => "Nack",
-            Self::Ack => "Ack",
-            Self::Note(m) => m.as_str(),
+            Self::PsbtRequest { psbt, memo } => {
+                write!(f, "PSBT request")?;
+                if let Some(memo) = memo {
+                    write!(f, " ({memo})")?;
+                }
+                write!(f, ": {psbt}")
+            }
+            Self::PsbtSigned { psbt } => write!(f, "Signed PSBT: {psbt}"),
+            Self::Ack { ref_event } => {
+                write!(f, "Ack")?;
+                if let Some(id) = ref_event {
+                    write!(f, " {id}")?;
+                }
+                Ok(())
+            }
+            Self::Nack { ref_event, reason } => {
+                write!(f, "Nack")?;
+                if let Some(id) = ref_event {
+                    write!(f, " {id}")?;
+                }
+                if let Some(reason) = reason {
+                    write!(f, ": {reason}")?;
+                }
+                Ok(())
+            }
+            Self::Note { text } => text.fmt(f),
         }
     }
 }
 
-impl fmt::Display for CallTy {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.as_ref())
+/// Payload of a call, serialized as JSON and split into [`Chunk`]s.
+///
+/// Binds a [`CallTy`] to the time it was created and a random nonce. Both are encrypted
+/// along with the call, so a recipient that remembers the nonces seen from each sender can
+/// reject a call that is replayed, and one that is stale.
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct CallPayload {
+    /// Random nonce, hex encoded
+    pub nonce: String,
+    /// Unix time the call was created
+    pub created_at: u64,
+    /// Unix time after which the call is expired, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Id of the event carrying the call this one replies to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    /// Whether the call is broadcast to every participant
+    #[serde(default)]
+    pub broadcast: bool,
+    /// Call
+    #[serde(flatten)]
+    pub call: CallTy,
+}
+
+impl CallPayload {
+    /// Maximum age in seconds of a call that is still fresh, currently one week.
+    ///
+    /// Nonces are remembered for as long, so older calls are rejected rather than checked for
+    /// replay. Calls sent while away for longer must be sent again.
+    pub const MAX_AGE: u64 = 7 * 24 * 60 * 60;
+
+    /// Maximum seconds a call may be created ahead of the local clock.
+    pub const MAX_SKEW: u64 = 10 * 60;
+
+    /// New payload for `call` created now with a random nonce.
+    pub fn new(call: CallTy) -> Self {
+        Self {
+            nonce: bitcoin::secp256k1::rand::random::<[u8; 16]>().to_lower_hex_string(),
+            created_at: std::time::UNIX_EPOCH
+                .elapsed()
+                .expect("system time should be after the epoch")
+                .as_secs(),
+            expires_at: None,
+            reply_to: None,
+            broadcast: false,
+            call,
+        }
+    }
+
+    /// Whether the call is expired as of unix time `now`.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
+
+    /// Id of the event the call replies to, falling back to the event acked or nacked.
+    pub fn parent(&self) -> Option<&str> {
+        let ref_event = match &self.call {
+            CallTy::Ack { ref_event } | CallTy::Nack { ref_event, .. } => ref_event.as_deref(),
+            _ => None,
+        };
+        self.reply_to.as_deref().or(ref_event)
+    }
+
+    /// Decode the decrypted and reassembled `payload` of a call, checking that it is fresh as
+    /// of unix time `now`.
+    ///
+    /// Errors if the call is stale.
+    pub fn decode(payload: &str, now: u64) -> Result<Self, CallError> {
+        let payload: Self =
+            ::serde_json::from_str(payload).map_err(|e| CallError::Payload(e.to_string()))?;
+        if payload.created_at.saturating_add(Self::MAX_AGE) < now
+            || payload.created_at > now.saturating_add(Self::MAX_SKEW)
+        {
+            return Err(CallError::Stale(payload.created_at));
+        }
+
+        Ok(payload)
+    }
+}
+
+/// Serde for a PSBT as a base64 string.
+mod psbt_base64 {
+    use std::str::FromStr;
+
+    use bdk_chain::bitcoin::Psbt;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(psbt: &Psbt, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(psbt)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Psbt, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Psbt::from_str(&s).map_err(D::Error::custom)
     }
 }
 
@@ -214,17 +544,309 @@ impl Call {
     }
 }
 
+impl Call {
+    /// Encode a call to `recipient` in the quorum with the given `fingerprint` carrying
+    /// `payload`, at the current version. See [`Call::parse`].
+    ///
+    /// Errors if the `recipient` id can't be encoded.
+    pub fn encode(fingerprint: &str, recipient: Pid, payload: &str) -> Result<Self, CallError> {
+        let recipient = recipient
+            .to_u16()
+            .ok_or_else(|| CallError::Recipient(recipient.to_string()))?;
+        let mut call = Call::new(crate::HRP);
+        call.push(&format!("{:02x}", Call::VERSION))
+            .push(fingerprint)
+            .push(&format!("{recipient:04x}"))
+            .build(payload);
+        Ok(call)
+    }
+}
+
+impl Call {
+    /// Current version of the wire format.
+    pub const VERSION: u8 = 5;
+
+    /// Length of the encoded version.
+    const VERSION_LEN: usize = 2;
+
+    /// Length of the quorum fingerprint.
+    const FINGERPRINT_LEN: usize = 8;
+
+    /// Length of the encoded recipient.
+    const RECIPIENT_LEN: usize = 4;
+
+    /// Parse a `Call` from a string of the form
+    /// `<hrp><version><fingerprint><recipient><payload>`, where the version is a 2-digit hex
+    /// byte, the fingerprint is 8 hex characters and the recipient is a 4-digit hex u16.
+    ///
+    /// Errors if the version isn't [`Call::VERSION`]. See [`Chunk`] and [`CallPayload`] for
+    /// the payload.
+    pub fn parse(s: &str) -> Result<ParsedCall, CallError> {
+        let s = s.strip_prefix(crate::HRP).ok_or(CallError::Hrp)?;
+
+        let (version, s) = split_at(s, Self::VERSION_LEN)?;
+        let version = u8::from_str_radix(version, 16)
+            .ok()
+            .filter(|_| version.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| CallError::Version(version.to_string()))?;
+        if version != Self::VERSION {
+            return Err(CallError::UnsupportedVersion(version));
+        }
+
+        let (fingerprint, s) = split_at(s, Self::FINGERPRINT_LEN)?;
+        if !fingerprint.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(CallError::Fingerprint(fingerprint.to_string()));
+        }
+
+        let (recipient, payload) = split_at(s, Self::RECIPIENT_LEN)?;
+        let recipient = u16::from_str_radix(recipient, 16)
+            .ok()
+            .filter(|_| recipient.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| CallError::Recipient(recipient.to_string()))?;
+
+        if payload.is_empty() {
+            return Err(CallError::EmptyPayload);
+        }
+
+        Ok(ParsedCall {
+            version,
+            fingerprint: fingerprint.to_string(),
+            recipient: u32::from(recipient).into(),
+            payload: payload.to_string(),
+        })
+    }
+}
+
+/// Split `s` at `mid`, failing if `s` is too short or `mid` isn't a char boundary.
+fn split_at(s: &str, mid: usize) -> Result<(&str, &str), CallError> {
+    s.split_at_checked(mid).ok_or(CallError::Truncated)
+}
+
 impl fmt::Display for Call {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.0.fmt(f)
     }
 }
 
+/// The parts of a [`Call`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedCall {
+    /// Wire format version
+    pub version: u8,
+    /// Quorum fingerprint
+    pub fingerprint: String,
+    /// Recipient participant id
+    pub recipient: Pid,
+    /// Payload
+    pub payload: String,
+}
+
+/// Error parsing a [`Call`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallError {
+    /// Missing the human readable prefix
+    Hrp,
+    /// The message ended before the payload
+    Truncated,
+    /// Invalid version
+    Version(String),
+    /// Unsupported version
+    UnsupportedVersion(u8),
+    /// Invalid quorum fingerprint
+    Fingerprint(String),
+    /// Invalid recipient id
+    Recipient(String),
+    /// Missing payload
+    EmptyPayload,
+    /// Invalid payload
+    Payload(String),
+    /// Checksum mismatch of the message with the given id
+    Checksum(String),
+    /// Call created at the given unix time is too old, or too far in the future
+    Stale(u64),
+    /// The signer failed to decrypt the call, e.g. a remote signer timed out
+    Signer(String),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hrp => write!(f, "missing prefix {}", crate::HRP),
+            Self::Truncated => write!(f, "call is truncated"),
+            Self::Version(s) => write!(f, "invalid version {s}"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported version {v}"),
+            Self::Fingerprint(s) => write!(f, "invalid quorum fingerprint {s}"),
+            Self::Recipient(s) => write!(f, "invalid recipient id {s}"),
+            Self::EmptyPayload => write!(f, "missing payload"),
+            Self::Payload(e) => write!(f, "invalid payload: {e}"),
+            Self::Checksum(id) => write!(f, "checksum mismatch for message {id}"),
+            Self::Stale(t) => write!(f, "stale call created at {t}"),
+            Self::Signer(e) => write!(f, "signer failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
 /// Chat entry.
 #[derive(Debug)]
 pub struct ChatEntry {
+    /// Sender participant id
+    pub pid: Pid,
     /// Sender alias
     pub alias: String,
-    /// Text message
-    pub message: String,
+    /// Id of the event carrying the call
+    pub event_id: String,
+    /// Unix time the event was created
+    pub created_at: u64,
+    /// Unix time after which the call is expired, if any. The earlier of the expiry in the
+    /// payload and the NIP-40 expiration of the event
+    pub expires_at: Option<u64>,
+    /// Id of the event carrying the call this one replies to, if any. See
+    /// [`CallPayload::parent`]
+    pub reply_to: Option<String>,
+    /// Whether the call is broadcast to every participant
+    pub broadcast: bool,
+    /// Call received. Plain text notes are read as [`CallTy::Note`]
+    pub call: CallTy,
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Unix time of the tests.
+    const NOW: u64 = 1_700_000_000;
+
+    /// Encoded payload of an ack created at unix time `created_at`.
+    fn payload(created_at: u64) -> String {
+        let payload = CallPayload {
+            created_at,
+            ..CallPayload::new(CallTy::Ack { ref_event: None })
+        };
+        serde_json::to_string(&payload).unwrap()
+    }
+
+    #[test]
+    fn decode_skew_boundary() {
+        let created_at = NOW + CallPayload::MAX_SKEW;
+        assert!(CallPayload::decode(&payload(created_at), NOW).is_ok());
+        assert_eq!(
+            CallPayload::decode(&payload(created_at + 1), NOW),
+            Err(CallError::Stale(created_at + 1))
+        );
+    }
+
+    #[test]
+    fn decode_stale_boundary() {
+        let created_at = NOW - CallPayload::MAX_AGE;
+        assert!(CallPayload::decode(&payload(created_at), NOW).is_ok());
+        assert_eq!(
+            CallPayload::decode(&payload(created_at - 1), NOW),
+            Err(CallError::Stale(created_at - 1))
+        );
+        // a call from last week is fresh, one from last fortnight isn't
+        let day = 24 * 60 * 60;
+        assert!(CallPayload::decode(&payload(NOW - 6 * day), NOW).is_ok());
+        assert_eq!(
+            CallPayload::decode(&payload(NOW - 14 * day), NOW),
+            Err(CallError::Stale(NOW - 14 * day))
+        );
+    }
+
+    #[test]
+    fn decode_extreme_times_without_overflow() {
+        assert_eq!(
+            CallPayload::decode(&payload(u64::MAX), NOW),
+            Err(CallError::Stale(u64::MAX))
+        );
+        assert_eq!(CallPayload::decode(&payload(0), u64::MAX), Err(CallError::Stale(0)));
+        assert!(CallPayload::decode(&payload(u64::MAX), u64::MAX).is_ok());
+    }
+
+    /// A valid call to participant 1 of quorum `0badf00d`, with payload `x`.
+    fn call() -> String {
+        format!("{}{:02x}0badf00d0001x", crate::HRP, Call::VERSION)
+    }
+
+    #[test]
+    fn parse_call() {
+        let parsed = Call::parse(&call()).unwrap();
+        assert_eq!(parsed.version, Call::VERSION);
+        assert_eq!(parsed.fingerprint, "0badf00d");
+        assert_eq!(parsed.recipient, Pid::from(1));
+        assert_eq!(parsed.payload, "x");
+    }
+
+    #[test]
+    fn reject_malformed_calls() {
+        let v = format!("{:02x}", Call::VERSION);
+        let hrp = crate::HRP;
+        let cases = [
+            (format!("loon2{v}0badf00d0001x"), CallError::Hrp),
+            (format!("{hrp}zz0badf00d0001x"), CallError::Version("zz".into())),
+            (format!("{hrp}+50badf00d0001x"), CallError::Version("+5".into())),
+            (
+                format!("{hrp}{v}0badf0od0001x"),
+                CallError::Fingerprint("0badf0od".into()),
+            ),
+            (format!("{hrp}{v}0badf00d00g1x"), CallError::Recipient("00g1".into())),
+            (format!("{hrp}{v}0badf00d+001x"), CallError::Recipient("+001".into())),
+            (format!("{hrp}{v}0badf00d0001"), CallError::EmptyPayload),
+            (format!("{hrp}{v}0badf00d000"), CallError::Truncated),
+            (format!("{hrp}{v}0badf00€0001x"), CallError::Truncated),
+            (format!("{hrp}04"), CallError::UnsupportedVersion(4)),
+        ];
+        for (s, e) in cases {
+            assert_eq!(Call::parse(&s), Err(e), "{s}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn call_round_trip(
+            fingerprint in "[0-9a-f]{8}",
+            recipient in any::<u16>(),
+            payload in "\\PC+"
+        ) {
+            let pid = Pid::from(u32::from(recipient));
+            let call = Call::encode(&fingerprint, pid, &payload).unwrap();
+            let parsed = Call::parse(&call.to_string()).unwrap();
+            prop_assert_eq!(parsed, ParsedCall {
+                version: Call::VERSION,
+                fingerprint,
+                recipient: pid,
+                payload,
+            });
+        }
+
+        #[test]
+        fn reject_recipient_beyond_u16(recipient in u32::from(u16::MAX) + 1..) {
+            let pid = Pid::from(recipient);
+            prop_assert_eq!(
+                Call::encode("0badf00d", pid, "x").unwrap_err(),
+                CallError::Recipient(pid.to_string())
+            );
+        }
+
+        #[test]
+        fn reject_other_versions(version in any::<u8>()) {
+            prop_assume!(version != Call::VERSION);
+            let s = format!("{}{version:02x}0badf00d0001x", crate::HRP);
+            prop_assert_eq!(Call::parse(&s), Err(CallError::UnsupportedVersion(version)));
+        }
+
+        #[test]
+        fn reject_truncated_calls(len in 0..call().len()) {
+            prop_assert!(Call::parse(&call()[..len]).is_err());
+        }
+
+        #[test]
+        fn parse_arbitrary_strings(s in "\\PC*") {
+            // errors rather than panics
+            let _ = Call::parse(&s);
+        }
+    }
 }
@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use bdk_chain::bitcoin::{
+    hashes::{sha256, Hash},
+    hex::DisplayHex,
+    secp256k1::rand,
+};
+use serde::{Deserialize, Serialize};
+
+use super::CallError;
+
+/// Maximum length in bytes of the data carried by a single [`Chunk`].
+///
+/// This keeps each encrypted part well within the event size limits of common relays.
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+/// How long to wait for the remaining parts of a message before dropping it.
+pub const CHUNK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Part of a message that is too large to be sent in a single call.
+///
+/// The payload of a [`Call`](super::Call) is a `Chunk` serialized as JSON and nip44 encrypted
+/// to the recipient. The message is recovered by concatenating the data of parts `0..total`
+/// and comparing the checksum.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// Message id, shared by all parts of a message
+    pub id: String,
+    /// Index of this part
+    pub part: u16,
+    /// Total count of parts
+    pub total: u16,
+    /// Checksum of the whole message
+    pub checksum: String,
+    /// Data
+    pub data: String,
+}
+
+impl Chunk {
+    /// Split `message` into chunks of at most [`CHUNK_SIZE`] bytes.
+    pub fn split(message: &str) -> Result<Vec<Self>, CallError> {
+        let mut parts = vec![];
+        let mut rest = message;
+        while !rest.is_empty() || parts.is_empty() {
+            let mut mid = rest.len().min(CHUNK_SIZE);
+            while !rest.is_char_boundary(mid) {
+                mid -= 1;
+            }
+            let (data, tail) = rest.split_at(mid);
+            parts.push(data);
+            rest = tail;
+        }
+        let total = u16::try_from(parts.len())
+            .map_err(|_| CallError::Payload(format!("message too long: {}", message.len())))?;
+
+        let id = rand::random::<[u8; 8]>().to_lower_hex_string();
+        let checksum = checksum(message);
+
+        Ok(parts
+            .into_iter()
+            .enumerate()
+            .map(|(part, data)| Self {
+                id: id.clone(),
+                part: part as u16,
+                total,
+                checksum: checksum.clone(),
+                data: data.to_string(),
+            })
+            .collect())
+    }
+}
+
+/// Checksum of a message, the first 4 bytes of the sha256 hash as hex.
+pub fn checksum(message: &str) -> String {
+    sha256::Hash::hash(message.as_bytes()).to_byte_array()[..4].to_lower_hex_string()
+}
+
+/// A message for which some parts are still missing.
+#[derive(Debug)]
+struct Partial {
+    total: u16,
+    checksum: String,
+    parts: BTreeMap<u16, String>,
+    /// Unix time the first part was received
+    first_seen: u64,
+}
+
+/// Reassembles messages from [`Chunk`]s received in any order.
+#[derive(Debug)]
+pub struct Reassembler {
+    /// Partial messages keyed by sender and message id
+    pending: HashMap<(String, String), Partial>,
+    timeout: Duration,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new(CHUNK_TIMEOUT)
+    }
+}
+
+impl Reassembler {
+    /// New `Reassembler` that drops partial messages after `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// Insert a `chunk` from `sender` received at unix time `now`.
+    ///
+    /// Returns the message once all of its parts have been received.
+    pub fn insert(
+        &mut self,
+        sender: &str,
+        chunk: Chunk,
+        now: u64,
+    ) -> Result<Option<String>, CallError> {
+        let Chunk {
+            id,
+            part,
+            total,
+            checksum: sum,
+            data,
+        } = chunk;
+        if part >= total {
+            return Err(CallError::Payload(format!("part {part} of {total}")));
+        }
+
+        let key = (sender.to_string(), id);
+        let partial = self.pending.entry(key.clone()).or_insert_with(|| Partial {
+            total,
+            checksum: sum.clone(),
+            parts: BTreeMap::new(),
+            first_seen: now,
+        });
+        if partial.total != total || partial.checksum != sum {
+            return Err(CallError::Payload(format!("inconsistent parts of {}", key.1)));
+        }
+        partial.parts.insert(part, data);
+
+        if partial.parts.len() < usize::from(partial.total) {
+            return Ok(None);
+        }
+
+        let partial = self.pending.remove(&key).expect("must have partial");
+        let message: String = partial.parts.into_values().collect();
+        if checksum(&message) != partial.checksum {
+            return Err(CallError::Checksum(key.1));
+        }
+
+        Ok(Some(message))
+    }
+
+    /// Drop partial messages first seen more than the timeout before unix time `now`,
+    /// returning the ids of the messages dropped.
+    pub fn expire(&mut self, now: u64) -> Vec<String> {
+        let timeout = self.timeout.as_secs();
+        let mut expired = vec![];
+        self.pending.retain(|(_, id), partial| {
+            let keep = now.saturating_sub(partial.first_seen) < timeout;
+            if !keep {
+                expired.push(id.clone());
+            }
+            keep
+        });
+        expired
+    }
+
+    /// Count of messages waiting for more parts.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reassemble(chunks: impl IntoIterator<Item = Chunk>) -> Result<Option<String>, CallError> {
+        let mut reassembler = Reassembler::default();
+        let mut ret = None;
+        for chunk in chunks {
+            ret = reassembler.insert("alice", chunk, 0)?;
+        }
+        Ok(ret)
+    }
+
+    #[test]
+    fn split_on_char_boundaries() {
+        // 3 bytes each, so CHUNK_SIZE isn't a boundary
+        let message = "€".repeat(CHUNK_SIZE);
+        let chunks = Chunk::split(&message).unwrap();
+        assert!(chunks.len() > 3);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert!(chunk.data.len() <= CHUNK_SIZE);
+            assert_eq!(usize::from(chunk.part), i);
+            assert_eq!(usize::from(chunk.total), chunks.len());
+            assert_eq!(chunk.id, chunks[0].id);
+            assert_eq!(chunk.checksum, checksum(&message));
+        }
+        let data: String = chunks.iter().map(|chunk| chunk.data.as_str()).collect();
+        assert_eq!(data, message);
+    }
+
+    #[test]
+    fn split_empty_message() {
+        let chunks = Chunk::split("").unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].total, 1);
+        assert_eq!(reassemble(chunks).unwrap().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn reassemble_out_of_order() {
+        let message = "a".repeat(3 * CHUNK_SIZE);
+        let mut chunks = Chunk::split(&message).unwrap();
+        chunks.reverse();
+        let mut reassembler = Reassembler::default();
+        let last = chunks.pop().unwrap();
+        for chunk in chunks {
+            assert_eq!(reassembler.insert("alice", chunk, 0).unwrap(), None);
+        }
+        assert_eq!(reassembler.pending(), 1);
+        assert_eq!(reassembler.insert("alice", last, 0).unwrap(), Some(message));
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn reject_part_out_of_range() {
+        let mut chunks = Chunk::split("hello").unwrap();
+        chunks[0].part = 1;
+        assert!(matches!(reassemble(chunks), Err(CallError::Payload(_))));
+    }
+
+    #[test]
+    fn reject_checksum_mismatch() {
+        let mut chunks = Chunk::split(&"a".repeat(CHUNK_SIZE + 1)).unwrap();
+        chunks[1].data = "b".to_string();
+        let id = chunks[0].id.clone();
+        assert!(matches!(reassemble(chunks), Err(CallError::Checksum(e)) if e == id));
+    }
+
+    #[test]
+    fn reject_inconsistent_parts() {
+        let mut chunks = Chunk::split(&"a".repeat(CHUNK_SIZE + 1)).unwrap();
+        chunks[1].total = 3;
+        assert!(matches!(reassemble(chunks), Err(CallError::Payload(_))));
+    }
+
+    #[test]
+    fn keep_senders_apart() {
+        let chunks = Chunk::split(&"a".repeat(CHUNK_SIZE + 1)).unwrap();
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.insert("alice", chunks[0].clone(), 0).unwrap(), None);
+        assert_eq!(reassembler.insert("bob", chunks[1].clone(), 0).unwrap(), None);
+        assert_eq!(reassembler.pending(), 2);
+    }
+
+    #[test]
+    fn expire_after_timeout() {
+        let chunks = Chunk::split(&"a".repeat(CHUNK_SIZE + 1)).unwrap();
+        let mut reassembler = Reassembler::new(Duration::from_secs(60));
+        reassembler.insert("alice", chunks[0].clone(), 100).unwrap();
+
+        assert!(reassembler.expire(159).is_empty());
+        assert_eq!(reassembler.pending(), 1);
+        assert_eq!(reassembler.expire(160), vec![chunks[0].id.clone()]);
+        assert_eq!(reassembler.pending(), 0);
+
+        // a late part starts over
+        assert_eq!(reassembler.insert("alice", chunks[1].clone(), 161).unwrap(), None);
+    }
+}
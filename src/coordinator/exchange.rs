@@ -0,0 +1,88 @@
+use nostr_sdk::{NostrSigner, PublicKey, SignerBackend};
+
+use super::{Call, CallError, CallPayload, Chunk, Pid};
+use crate::Error;
+
+/// A message from a quorum participant, as read by [`Call::open`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Opened {
+    /// Plain text note, not a call
+    Note(String),
+    /// Call for the quorum with the given fingerprint, other than ours
+    OtherQuorum(String),
+    /// Call for another participant of our quorum
+    NotForUs,
+    /// Part of a call for us, to be passed to a [`Reassembler`](super::Reassembler)
+    Chunk(Chunk),
+}
+
+impl Call {
+    /// Split `payload` into [`Chunk`]s and nip44 encrypt each one to each of the `recipients`
+    /// in the quorum with the given `fingerprint`, returning one call for each part for each
+    /// recipient.
+    ///
+    /// The recipients share the payload, nonce included, so a broadcast reads as one message.
+    pub async fn seal(
+        signer: &dyn NostrSigner,
+        fingerprint: &str,
+        payload: &CallPayload,
+        recipients: &[(Pid, PublicKey)],
+    ) -> Result<Vec<Self>, Error> {
+        let message =
+            serde_json::to_string(payload).map_err(|e| Error::Coordinator(e.to_string()))?;
+        let chunks = Chunk::split(&message).map_err(|e| Error::Coordinator(e.to_string()))?;
+
+        let mut calls = vec![];
+        for (pid, pk) in recipients {
+            for chunk in &chunks {
+                let chunk =
+                    serde_json::to_string(chunk).map_err(|e| Error::Coordinator(e.to_string()))?;
+                let payload = signer
+                    .nip44_encrypt(pk, &chunk)
+                    .await
+                    .map_err(|e| Error::Coordinator(e.to_string()))?;
+                let call = Self::encode(fingerprint, *pid, &payload)
+                    .map_err(|e| Error::Coordinator(e.to_string()))?;
+                calls.push(call);
+            }
+        }
+
+        Ok(calls)
+    }
+
+    /// Open a `message` from `sender` as participant `me` of the quorum with the given
+    /// `fingerprint`, decrypting the part of the call it carries if it is for us.
+    ///
+    /// Errors if the call is malformed or can't be decrypted. A remote signer failing is a
+    /// [`CallError::Signer`], as it may succeed if tried again.
+    pub async fn open(
+        signer: &dyn NostrSigner,
+        fingerprint: &str,
+        me: Pid,
+        sender: &PublicKey,
+        message: &str,
+    ) -> Result<Opened, CallError> {
+        if !message.starts_with(crate::HRP) {
+            return Ok(Opened::Note(message.to_string()));
+        }
+        let call = Self::parse(message)?;
+        if call.fingerprint != fingerprint {
+            return Ok(Opened::OtherQuorum(call.fingerprint));
+        }
+        if call.recipient != me {
+            return Ok(Opened::NotForUs);
+        }
+
+        let chunk = signer
+            .nip44_decrypt(sender, &call.payload)
+            .await
+            .map_err(|e| match signer.backend() {
+                // keys at hand only fail on a bad payload
+                SignerBackend::Keys => CallError::Payload(e.to_string()),
+                _ => CallError::Signer(e.to_string()),
+            })?;
+        let chunk = serde_json::from_str(&chunk).map_err(|e| CallError::Payload(e.to_string()))?;
+
+        Ok(Opened::Chunk(chunk))
+    }
+}
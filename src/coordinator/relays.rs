@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use nostr_sdk::nips::nip65::{self, RelayMetadata};
+use nostr_sdk::{Event, Filter, Kind};
+
+use super::{Coordinator, Pid};
+use crate::Error;
+
+/// Relay used if none are configured for an account.
+pub const DEFAULT_RELAY: &str = "wss://relay.damus.io";
+
+/// How long to wait for relays when fetching relay lists.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Relays a participant reads from and writes to, as advertised by their NIP-65 relay list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelayHints {
+    /// Relays the participant reads from, i.e. where to publish calls for them
+    pub read: Vec<String>,
+    /// Relays the participant writes to, i.e. where to fetch their calls from
+    pub write: Vec<String>,
+}
+
+impl RelayHints {
+    /// Read the hints from a NIP-65 relay list `event`. A relay without a marker is both read
+    /// and written to.
+    pub fn from_event(event: &Event) -> Self {
+        let mut hints = Self::default();
+        for (url, metadata) in nip65::extract_relay_list(event) {
+            let url = url.to_string();
+            match metadata {
+                Some(RelayMetadata::Read) => hints.read.push(url),
+                Some(RelayMetadata::Write) => hints.write.push(url),
+                None => {
+                    hints.read.push(url.clone());
+                    hints.write.push(url);
+                }
+            }
+        }
+
+        hints
+    }
+}
+
+impl Coordinator {
+    /// Fetch the newest NIP-65 relay list of each quorum participant from the connected
+    /// relays. Participants without a relay list are left out.
+    pub async fn relay_hints(&self) -> Result<BTreeMap<Pid, RelayHints>, Error> {
+        let filter = Filter::new()
+            .kind(Kind::RelayList)
+            .authors(self.participants().map(|(_, p)| p.pk));
+        self.client.connect().await;
+        let events = self.client.fetch_events(filter, TIMEOUT).await.map_err(Error::Nostr)?;
+
+        let mut ret = BTreeMap::new();
+        for (pid, p) in self.participants() {
+            let newest = events
+                .iter()
+                .filter(|event| event.pubkey == p.pk)
+                .max_by_key(|event| event.created_at);
+            if let Some(event) = newest {
+                ret.insert(*pid, RelayHints::from_event(event));
+            }
+        }
+
+        Ok(ret)
+    }
+}
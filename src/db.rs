@@ -5,6 +5,7 @@ pub struct Account {
     pub network: String,
     pub nick: String,
     pub descriptor: String,
+    pub transport: String,
 }
 
 /// Represents a row in table 'friend'.
@@ -15,3 +16,30 @@ pub struct Friend {
     pub npub: String,
     pub alias: Option<String>,
 }
+
+/// Represents a row in table 'hook'.
+#[derive(Debug)]
+pub struct Hook {
+    pub id: u32,
+    pub account_id: u32,
+    pub event: String,
+    pub command: Option<String>,
+    pub url: Option<String>,
+}
+
+/// Represents a row in table 'inbox'.
+#[derive(Debug)]
+pub struct InboxEntry {
+    pub event_id: String,
+    pub account_id: u32,
+    pub pid: u32,
+    pub created_at: u64,
+    pub ty: String,
+    pub payload: String,
+    pub read: bool,
+    pub expires_at: Option<u64>,
+    pub archived: bool,
+    pub reply_to: Option<String>,
+    pub broadcast: bool,
+    pub response: Option<String>,
+}
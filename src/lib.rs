@@ -2,10 +2,20 @@ use std::fmt;
 
 mod coordinator;
 mod db;
+mod messenger;
+#[cfg(feature = "zmq")]
+mod notify;
+#[cfg(feature = "test-relay")]
+mod test_relay;
 mod wallet;
 
 pub use coordinator::*;
 pub use db::*;
+pub use messenger::*;
+#[cfg(feature = "zmq")]
+pub use notify::*;
+#[cfg(feature = "test-relay")]
+pub use test_relay::*;
 pub use wallet::*;
 
 // Re-exports
@@ -21,6 +31,8 @@ pub use {
 pub const BDK_DB_PREFIX: &str = "wallet";
 /// Path to Loon database.
 pub const LOON_DB_PATH: &str = "loon.db";
+/// Directory of the encrypted nostr keys of each account.
+pub const KEYSTORE_DIR: &str = "keys";
 /// Human-readable part of a loon call
 pub const HRP: &str = "loon1";
 
@@ -29,17 +41,28 @@ pub const HRP: &str = "loon1";
 pub enum Error {
     /// Coordinator
     Coordinator(String),
+    /// Chain source
+    Chain(String),
+    /// Messenger
+    Messenger(String),
     /// Nostr client
     #[cfg(feature = "nostr-sdk")]
     Nostr(nostr_sdk::client::Error),
+    /// ZMQ
+    #[cfg(feature = "zmq")]
+    Zmq(zmq::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Coordinator(e) => e.fmt(f),
+            Self::Chain(e) => e.fmt(f),
+            Self::Messenger(e) => e.fmt(f),
             #[cfg(feature = "nostr-sdk")]
             Self::Nostr(e) => e.fmt(f),
+            #[cfg(feature = "zmq")]
+            Self::Zmq(e) => e.fmt(f),
         }
     }
 }
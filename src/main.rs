@@ -9,7 +9,7 @@ use bdk_core::{ConfirmationBlockTime, Merge};
 
 use bdk_chain::{
     bdk_core, bitcoin, keychain_txout::KeychainTxOutIndex, local_chain::LocalChain, miniscript,
-    DescriptorExt, TxGraph,
+    TxGraph,
 };
 use clap::Parser;
 
@@ -17,7 +17,7 @@ use loon::{
     rand::{self, Fill},
     rusqlite,
     simplerpc::{self, jsonrpc},
-    Account, BdkChangeSet, BdkWallet, Coordinator, Keychain, BDK_DB_PREFIX, LOON_DB_PATH,
+    BdkChangeSet, BdkWallet, Coordinator, Keychain, BDK_DB_PREFIX,
 };
 #[cfg(feature = "nostr-sdk")]
 use nostr_sdk::prelude::*;
@@ -34,21 +34,15 @@ const DEFAULT_ACCOUNT_ID: u32 = 1;
 #[tokio::main]
 async fn main() -> cmd::Result<()> {
     let args = Args::parse();
+    let json = args.json;
 
     // Handle db command or generate keys
     match args.cmd {
         Cmd::Db(_) => {
-            cmd::db::execute(&args.cmd)?;
+            cmd::db::execute(&args.cmd, json)?;
             return Ok(());
         }
         Cmd::Generate(cmd) => match cmd {
-            #[cfg(feature = "nostr-sdk")]
-            GenerateSubCmd::Nsec => {
-                let keys = Keys::generate();
-                println!("{}", keys.public_key.to_bech32()?);
-                println!("{}", keys.secret_key().to_bech32()?);
-                return Ok(());
-            }
             GenerateSubCmd::Wif { test } => {
                 let network = if test {
                     NetworkKind::Test
@@ -64,28 +58,43 @@ async fn main() -> cmd::Result<()> {
                     network,
                     inner,
                 };
-                println!("{}", prv.to_wif());
+                if json {
+                    cmd::output::print_json(&serde_json::json!({ "wif": prv.to_wif() }))?;
+                } else {
+                    println!("{}", prv.to_wif());
+                }
                 return Ok(());
             }
         },
         _ => {}
     }
 
-    // Get descriptors from loon db
     let account_id = args.account_id.unwrap_or(DEFAULT_ACCOUNT_ID);
-    let db = rusqlite::Connection::open(LOON_DB_PATH)?;
-
-    let mut stmt = db.prepare("SELECT * FROM account WHERE id = ?1")?;
-    let mut rows = stmt.query_map([&account_id], |row| {
-        Ok(Account {
-            id: row.get(0)?,
-            network: row.get(1)?,
-            nick: row.get(2)?,
-            descriptor: row.get(3)?,
-        })
-    })?;
-    let account = match rows.next() {
-        Some(acct) => acct?,
+
+    #[cfg(feature = "nostr-sdk")]
+    if let Cmd::Key(subcmd) = &args.cmd {
+        return cmd::key::execute(account_id, subcmd, json);
+    }
+
+    // Prefer asking a running daemon over reopening the databases
+    if let Some(request) = cmd::daemon::Request::from_cmd(&args.cmd) {
+        match cmd::daemon::query(account_id, request, json).await? {
+            Some(response) => {
+                print!("{response}");
+                return Ok(());
+            }
+            None if request == cmd::daemon::Request::Status => {
+                bail!("no daemon running for account id {account_id}");
+            }
+            None => {}
+        }
+    }
+
+    // Get descriptors from loon db
+    let db = cmd::db::open()?;
+
+    let account = match cmd::db::get_account(&db, account_id)? {
+        Some(acct) => acct,
         None => {
             bail!("no account exists for account id {account_id}");
         }
@@ -97,8 +106,7 @@ async fn main() -> cmd::Result<()> {
     let mut desc_iter = desc.into_single_descriptors()?.into_iter();
     let desc = desc_iter.next().unwrap();
     let change_desc = desc_iter.next();
-    let did = desc.descriptor_id().to_string();
-    let quorum_fp = &did[..8];
+    let quorum_fp = cmd::descriptor::fingerprint(desc_str)?;
 
     let (network, rpc_port) = match account.network.as_str() {
         "signet" => (Network::Signet, 38332),
@@ -168,7 +176,8 @@ async fn main() -> cmd::Result<()> {
     #[cfg(not(feature = "nostr-sdk"))]
     let mut coordinator = {
         Coordinator {
-            fingerprint: quorum_fp.to_string(),
+            account_id: account.id,
+            fingerprint: quorum_fp.clone(),
             wallet,
             db: Arc::new(Mutex::new(conn)),
             rpc_client,
@@ -188,15 +197,33 @@ async fn main() -> cmd::Result<()> {
         })?;
 
         // Initialize nostr client
-        let nsec = Keys::parse(&env::var("NOSTR_NSEC").context("must set NOSTR_NSEC")?)?;
-        let client = Client::builder().signer(nsec).build();
-        client.add_relay("wss://relay.damus.io").await?;
+        let signer = cmd::signer::load(account.id)?;
+        let client = Client::builder().signer(signer).build();
+        // relays of the account, else from the environment, else the default
+        let mut relays = cmd::db::list_relays(&db, account.id)?;
+        if relays.is_empty() {
+            if let Ok(urls) = env::var("NOSTR_RELAYS") {
+                relays = urls
+                    .split(',')
+                    .map(|url| url.trim().to_string())
+                    .filter(|url| !url.is_empty())
+                    .collect();
+            }
+        }
+        if relays.is_empty() {
+            relays.push(loon::DEFAULT_RELAY.to_string());
+        }
+        for url in relays {
+            client.add_relay(&url).await?;
+        }
 
         let mut coordinator = Coordinator {
-            fingerprint: quorum_fp.to_string(),
+            account_id: account.id,
+            fingerprint: quorum_fp.clone(),
             wallet,
             participants: std::collections::BTreeMap::new(),
             client: Arc::new(client),
+            transport: account.transport.parse()?,
             db: Arc::new(Mutex::new(conn)),
             rpc_client,
         };
@@ -216,21 +243,37 @@ async fn main() -> cmd::Result<()> {
     match args.cmd {
         Cmd::Db(_) => unreachable!("handled above"),
         #[cfg(feature = "nostr-sdk")]
-        Cmd::Call(subcmd) => cmd::call::push(&coordinator, subcmd).await?,
-        Cmd::Desc(subcmd) => cmd::descriptor::execute(&coordinator, subcmd)?,
+        Cmd::Call(subcmd) => cmd::call::push(&coordinator, subcmd, json).await?,
+        #[cfg(feature = "nostr-sdk")]
+        Cmd::Crypto(subcmd) => cmd::crypto::execute(&coordinator, subcmd, json).await?,
+        Cmd::Daemon(opt) => cmd::daemon::run(coordinator, account.id, opt).await?,
+        Cmd::Desc(subcmd) => cmd::descriptor::execute(&coordinator, subcmd, json)?,
         #[cfg(feature = "nostr-sdk")]
-        Cmd::Fetch { listen } => {
+        Cmd::Fetch { listen, calls_only } => {
             if listen {
-                cmd::fetch::listen(&coordinator).await?;
+                cmd::fetch::listen(&coordinator, calls_only).await?;
             } else {
-                cmd::fetch::fetch_and_decrypt(&coordinator).await?;
+                cmd::fetch::fetch_and_decrypt(&coordinator, calls_only, json).await?;
             }
         }
         Cmd::Hash => {
-            println!("{}", coordinator.rpc_client().get_best_block_hash()?);
+            let hash = coordinator.rpc_client().get_best_block_hash()?;
+            if json {
+                cmd::output::print_json(&serde_json::json!({ "hash": hash.to_string() }))?;
+            } else {
+                println!("{hash}");
+            }
         }
         Cmd::Generate(..) => unreachable!("handled above"),
-        Cmd::Wallet(subcmd) => cmd::wallet::execute(&mut coordinator, subcmd).await?,
+        #[cfg(feature = "nostr-sdk")]
+        Cmd::Inbox(subcmd) => cmd::inbox::execute(&coordinator, subcmd, json)?,
+        #[cfg(feature = "nostr-sdk")]
+        Cmd::Key(_) => unreachable!("handled above"),
+        Cmd::Serve(opt) => cmd::serve::run(coordinator, opt).await?,
+        Cmd::Status => unreachable!("handled above"),
+        #[cfg(feature = "tui")]
+        Cmd::Tui => cmd::tui::run(&coordinator).await?,
+        Cmd::Wallet(subcmd) => cmd::wallet::execute(&mut coordinator, subcmd, json).await?,
     }
 
     Ok(())
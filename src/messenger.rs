@@ -0,0 +1,64 @@
+use std::future::Future;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+mod file;
+mod memory;
+#[cfg(feature = "nostr-sdk")]
+mod relay;
+pub use file::*;
+pub use memory::*;
+#[cfg(feature = "nostr-sdk")]
+pub use relay::*;
+
+/// A message received by a [`Messenger`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Message id, unique to the messenger
+    pub id: String,
+    /// Identity of the sender
+    pub sender: String,
+    /// Unix time the message was created
+    pub created_at: u64,
+    /// Unix time after which the message is expired, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Content, typically a [`Call`](crate::Call)
+    pub content: String,
+}
+
+/// Current unix time.
+fn now() -> u64 {
+    std::time::UNIX_EPOCH
+        .elapsed()
+        .expect("system time should be after the epoch")
+        .as_secs()
+}
+
+/// Sends and receives messages on behalf of a quorum participant.
+///
+/// Identities are opaque strings meaningful to the messenger, e.g. a hex encoded nostr
+/// public key.
+pub trait Messenger {
+    /// Identity of the local participant, by which peers address messages to it.
+    fn identity(&self) -> &str;
+
+    /// Send `content` to `recipient`, expiring at unix time `expires_at` if given, returning
+    /// the id of the message.
+    fn send(
+        &self,
+        recipient: &str,
+        content: &str,
+        expires_at: Option<u64>,
+    ) -> impl Future<Output = Result<String, Error>> + Send;
+
+    /// Fetch messages for the local participant sent by any of `peers` at or after unix time
+    /// `since`, leaving out expired messages.
+    fn fetch_since(
+        &self,
+        peers: &[String],
+        since: u64,
+    ) -> impl Future<Output = Result<Vec<Envelope>, Error>> + Send;
+}
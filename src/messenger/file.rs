@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use bdk_chain::bitcoin::{hex::DisplayHex, secp256k1::rand};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::{now, Envelope, Messenger};
+use crate::Error;
+
+/// A message as written to the shared directory.
+#[derive(Debug, Serialize, Deserialize)]
+struct Letter {
+    recipient: String,
+    #[serde(flatten)]
+    envelope: Envelope,
+}
+
+/// A [`Messenger`] that drops messages as files in a shared directory, e.g. a USB drive
+/// carried to an air-gapped machine or a synced folder.
+///
+/// Each message is written to its own JSON file named by the message id.
+#[derive(Debug, Clone)]
+pub struct FileMessenger {
+    identity: String,
+    dir: PathBuf,
+}
+
+impl FileMessenger {
+    /// New `FileMessenger` for `identity` using the shared directory `dir`.
+    pub fn new(identity: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            identity: identity.into(),
+            dir: dir.into(),
+        }
+    }
+}
+
+impl Messenger for FileMessenger {
+    fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    async fn send(
+        &self,
+        recipient: &str,
+        content: &str,
+        expires_at: Option<u64>,
+    ) -> Result<String, Error> {
+        let id = rand::random::<[u8; 16]>().to_lower_hex_string();
+        let created_at = now();
+        let letter = Letter {
+            recipient: recipient.to_string(),
+            envelope: Envelope {
+                id: id.clone(),
+                sender: self.identity.clone(),
+                created_at,
+                expires_at,
+                content: content.to_string(),
+            },
+        };
+        let json = serde_json::to_vec(&letter).map_err(|e| Error::Messenger(e.to_string()))?;
+
+        // Write to a temporary file first so readers never see a partial message.
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| Error::Messenger(e.to_string()))?;
+        let tmp = self.dir.join(format!(".{id}.tmp"));
+        fs::write(&tmp, json)
+            .await
+            .map_err(|e| Error::Messenger(e.to_string()))?;
+        fs::rename(&tmp, self.dir.join(format!("{id}.json")))
+            .await
+            .map_err(|e| Error::Messenger(e.to_string()))?;
+
+        Ok(id)
+    }
+
+    async fn fetch_since(&self, peers: &[String], since: u64) -> Result<Vec<Envelope>, Error> {
+        let now = now();
+        let mut ret = vec![];
+        let mut entries = fs::read_dir(&self.dir)
+            .await
+            .map_err(|e| Error::Messenger(e.to_string()))?;
+
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| Error::Messenger(e.to_string()))?
+        {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Ok(bytes) = fs::read(&path).await else {
+                continue;
+            };
+            // Skip files that aren't messages
+            let Ok(Letter {
+                recipient,
+                envelope,
+            }) = serde_json::from_slice(&bytes)
+            else {
+                continue;
+            };
+            if recipient == self.identity
+                && peers.contains(&envelope.sender)
+                && envelope.created_at >= since
+                && envelope.expires_at.is_none_or(|t| t > now)
+            {
+                ret.push(envelope);
+            }
+        }
+        ret.sort_by_key(|envelope| envelope.created_at);
+
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fetch_messages_dropped_in_dir() {
+        let dir = std::env::temp_dir().join(format!(
+            "loon-file-messenger-{}",
+            rand::random::<[u8; 8]>().to_lower_hex_string()
+        ));
+        let alice = FileMessenger::new("alice", &dir);
+        let bob = FileMessenger::new("bob", &dir);
+        let id = alice.send("bob", "hi bob", None).await.unwrap();
+        alice.send("carol", "hi carol", None).await.unwrap();
+        // files that aren't messages are skipped
+        fs::write(dir.join("notes.json"), "{}").await.unwrap();
+
+        let received = bob.fetch_since(&["alice".to_string()], 0).await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].id, id);
+        assert_eq!(received[0].content, "hi bob");
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::{Arc, Mutex};
+
+use super::{now, Envelope, Messenger};
+use crate::Error;
+
+/// Messages exchanged by [`MemoryMessenger`]s, with the identity of each recipient.
+pub type MemoryBoard = Arc<Mutex<Vec<(String, Envelope)>>>;
+
+/// A [`Messenger`] that keeps messages in memory, useful for testing.
+///
+/// Messengers created with a clone of the same [`MemoryBoard`] can message each other.
+#[derive(Debug, Clone)]
+pub struct MemoryMessenger {
+    identity: String,
+    board: MemoryBoard,
+}
+
+impl MemoryMessenger {
+    /// New `MemoryMessenger` for `identity` sharing the given `board`.
+    pub fn new(identity: impl Into<String>, board: MemoryBoard) -> Self {
+        Self {
+            identity: identity.into(),
+            board,
+        }
+    }
+}
+
+impl Messenger for MemoryMessenger {
+    fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    async fn send(
+        &self,
+        recipient: &str,
+        content: &str,
+        expires_at: Option<u64>,
+    ) -> Result<String, Error> {
+        let mut board = self.board.lock().unwrap();
+        let id = board.len().to_string();
+        let created_at = now();
+        board.push((
+            recipient.to_string(),
+            Envelope {
+                id: id.clone(),
+                sender: self.identity.clone(),
+                created_at,
+                expires_at,
+                content: content.to_string(),
+            },
+        ));
+
+        Ok(id)
+    }
+
+    async fn fetch_since(&self, peers: &[String], since: u64) -> Result<Vec<Envelope>, Error> {
+        let board = self.board.lock().unwrap();
+        let now = now();
+
+        Ok(board
+            .iter()
+            .filter(|(recipient, envelope)| {
+                recipient == &self.identity
+                    && peers.contains(&envelope.sender)
+                    && envelope.created_at >= since
+                    && envelope.expires_at.is_none_or(|t| t > now)
+            })
+            .map(|(_, envelope)| envelope.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fetch_messages_to_us_from_peers() {
+        let board = MemoryBoard::default();
+        let alice = MemoryMessenger::new("alice", board.clone());
+        let bob = MemoryMessenger::new("bob", board.clone());
+        let carol = MemoryMessenger::new("carol", board);
+        alice.send("bob", "hi bob", None).await.unwrap();
+        alice.send("carol", "hi carol", None).await.unwrap();
+        carol.send("bob", "hi from carol", None).await.unwrap();
+
+        let peers = ["alice".to_string()];
+        let received = bob.fetch_since(&peers, 0).await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].sender, "alice");
+        assert_eq!(received[0].content, "hi bob");
+
+        let received = carol.fetch_since(&peers, 0).await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].content, "hi carol");
+
+        let peers = ["alice".to_string(), "carol".to_string()];
+        assert_eq!(bob.fetch_since(&peers, 0).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn leave_out_old_and_expired_messages() {
+        let board = MemoryBoard::default();
+        let alice = MemoryMessenger::new("alice", board.clone());
+        let bob = MemoryMessenger::new("bob", board);
+        let now = now();
+        alice.send("bob", "expired", Some(now)).await.unwrap();
+        alice.send("bob", "fresh", Some(now + 60)).await.unwrap();
+
+        let peers = ["alice".to_string()];
+        let received = bob.fetch_since(&peers, now).await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].content, "fresh");
+        assert_eq!(received[0].expires_at, Some(now + 60));
+
+        assert!(bob.fetch_since(&peers, now + 60).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use nostr_sdk::nips::nip59::UnwrappedGift;
+use nostr_sdk::{
+    prelude::Output, Client, Event, EventBuilder, EventId, Filter, Kind, PublicKey, RelayUrl, Tag,
+    Tags, Timestamp,
+};
+use serde::Serialize;
+
+use super::{now, Envelope, Messenger};
+use crate::{Error, Transport};
+
+/// How long to wait for relays when fetching.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most events asked of a relay at once, see [`NostrMessenger::fetch_pages`].
+const PAGE_LIMIT: usize = 500;
+
+/// How far gift wraps may be backdated, per NIP-59.
+const GIFT_WRAP_SKEW: u64 = 2 * 24 * 60 * 60;
+
+/// Outcome of publishing a message to each relay.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sent {
+    /// Event id
+    pub id: String,
+    /// Relays that accepted the event
+    pub success: Vec<String>,
+    /// Relays that failed, with the reason
+    pub failed: BTreeMap<String, String>,
+}
+
+impl From<Output<EventId>> for Sent {
+    fn from(output: Output<EventId>) -> Self {
+        Self {
+            id: output.val.to_hex(),
+            success: output.success.iter().map(|url| url.to_string()).collect(),
+            failed: output
+                .failed
+                .iter()
+                .map(|(url, e)| (url.to_string(), e.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// A [`Messenger`] over nostr relays. Identities are hex encoded public keys.
+#[derive(Debug, Clone)]
+pub struct NostrMessenger {
+    client: Arc<Client>,
+    identity: String,
+    transport: Transport,
+    read_relays: HashMap<String, Vec<String>>,
+    fetch_relays: Option<Vec<RelayUrl>>,
+}
+
+impl NostrMessenger {
+    /// New `NostrMessenger` sending with the given `transport`, identified by the public key
+    /// of the signer of `client`.
+    pub async fn new(client: Arc<Client>, transport: Transport) -> Result<Self, Error> {
+        let signer = client.signer().await.map_err(Error::Nostr)?;
+        let pk = signer
+            .get_public_key()
+            .await
+            .map_err(|e| Error::Messenger(e.to_string()))?;
+
+        Ok(Self {
+            client,
+            identity: pk.to_hex(),
+            transport,
+            read_relays: HashMap::new(),
+            fetch_relays: None,
+        })
+    }
+
+    /// Also publish messages to the relays each recipient reads from, keyed by the identity
+    /// of the recipient. See [`RelayHints`](crate::RelayHints).
+    pub fn with_read_relays(mut self, read_relays: HashMap<String, Vec<String>>) -> Self {
+        self.read_relays = read_relays;
+        self
+    }
+
+    /// Only fetch from `relays` rather than from every relay of the client, e.g. to keep track
+    /// of the position reached in each relay.
+    pub fn with_fetch_relays(mut self, relays: Vec<RelayUrl>) -> Self {
+        self.fetch_relays = Some(relays);
+        self
+    }
+
+    /// Publish `content` as a text note, or as a gift wrapped private message to `recipient`,
+    /// depending on the transport. The expiry is set as a NIP-40 expiration tag.
+    ///
+    /// The message is sent to our relays and the relays `recipient` reads from, returning the
+    /// outcome for each relay. Errors if no relay accepted the message.
+    pub async fn publish(
+        &self,
+        recipient: &str,
+        content: &str,
+        expires_at: Option<u64>,
+    ) -> Result<Sent, Error> {
+        let mut urls = self.client.relays().await.into_keys().collect::<Vec<_>>();
+        for url in self.read_relays.get(recipient).into_iter().flatten() {
+            // skip bad hints rather than failing the send
+            let Ok(url) = RelayUrl::parse(url) else {
+                continue;
+            };
+            if !urls.contains(&url) {
+                self.client.add_relay(&url).await.map_err(Error::Nostr)?;
+                urls.push(url);
+            }
+        }
+        self.client.connect().await;
+
+        let tags = expires_at.map(|t| Tag::expiration(Timestamp::from(t)));
+        let output = match self.transport {
+            Transport::Note => {
+                let builder = EventBuilder::new(Kind::TextNote, content).tags(tags);
+                self.client.send_event_builder_to(urls, builder).await
+            }
+            Transport::GiftWrap => {
+                let pk = public_key(recipient)?;
+                self.client.send_private_msg_to(urls, pk, content, tags).await
+            }
+        }
+        .map_err(Error::Nostr)?;
+
+        Ok(output.into())
+    }
+
+    /// Fetch the events matching `filter` a page at a time, from the newest back, so that none
+    /// are left out by the result limit of a relay. A page short of the limit is the last.
+    async fn fetch_pages(&self, filter: Filter) -> Result<Vec<Event>, Error> {
+        let mut events = vec![];
+        let mut ids = HashSet::new();
+        let mut until = None;
+        loop {
+            let mut page = filter.clone().limit(PAGE_LIMIT);
+            if let Some(until) = until {
+                page = page.until(until);
+            }
+            let page = match &self.fetch_relays {
+                Some(urls) => self.client.fetch_events_from(urls.clone(), page, TIMEOUT).await,
+                None => self.client.fetch_events(page, TIMEOUT).await,
+            }
+            .map_err(Error::Nostr)?;
+            let full = page.len() >= PAGE_LIMIT;
+            let Some(oldest) = page.iter().map(|event| event.created_at.as_secs()).min() else {
+                break;
+            };
+            let mut new = false;
+            for event in page {
+                if ids.insert(event.id) {
+                    events.push(event);
+                    new = true;
+                }
+            }
+            if !full {
+                break;
+            }
+            // `until` is inclusive, so the oldest second is asked again unless all of it was
+            // already seen
+            let next = if new {
+                Some(oldest)
+            } else {
+                oldest.checked_sub(1)
+            };
+            let Some(next) = next else {
+                break;
+            };
+            until = Some(Timestamp::from(next));
+        }
+
+        Ok(events)
+    }
+}
+
+/// Unix time of the NIP-40 expiration in `tags`, if any.
+fn expiration(tags: &Tags) -> Option<u64> {
+    tags.expiration().map(|t| t.as_secs())
+}
+
+/// Parse a hex encoded public key.
+fn public_key(s: &str) -> Result<PublicKey, Error> {
+    PublicKey::from_hex(s).map_err(|e| Error::Messenger(e.to_string()))
+}
+
+impl Messenger for NostrMessenger {
+    fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// See [`NostrMessenger::publish`].
+    async fn send(
+        &self,
+        recipient: &str,
+        content: &str,
+        expires_at: Option<u64>,
+    ) -> Result<String, Error> {
+        Ok(self.publish(recipient, content, expires_at).await?.id)
+    }
+
+    /// Fetch text notes authored by `peers` and gift wraps addressed to us, regardless of the
+    /// transport, paging past the result limit of the relays. Errors if either can't be
+    /// fetched, so that no message is missed by a caller resuming from the newest message
+    /// returned.
+    async fn fetch_since(&self, peers: &[String], since: u64) -> Result<Vec<Envelope>, Error> {
+        let peers = peers.iter().map(|s| public_key(s)).collect::<Result<Vec<_>, _>>()?;
+        let me = public_key(&self.identity)?;
+        let now = now();
+        self.client.connect().await;
+
+        let notes = Filter::new()
+            .kind(Kind::TextNote)
+            .authors(peers.clone())
+            .since(since.into());
+        // gift wraps are backdated, so look further back
+        let wraps = Filter::new()
+            .kind(Kind::GiftWrap)
+            .pubkey(me)
+            .since(since.saturating_sub(GIFT_WRAP_SKEW).into());
+
+        let mut ret = vec![];
+        for filter in [notes, wraps] {
+            for event in self.fetch_pages(filter).await? {
+                let expires_at = expiration(&event.tags);
+                if expires_at.is_some_and(|t| t <= now) {
+                    continue;
+                }
+                if event.kind == Kind::TextNote {
+                    ret.push(Envelope {
+                        id: event.id.to_hex(),
+                        sender: event.pubkey.to_hex(),
+                        created_at: event.created_at.as_secs(),
+                        expires_at,
+                        content: event.content,
+                    });
+                    continue;
+                }
+                // Skip gift wraps we can't unwrap
+                let Ok(UnwrappedGift { sender, rumor }) =
+                    self.client.unwrap_gift_wrap(&event).await
+                else {
+                    continue;
+                };
+                let expires_at = expiration(&rumor.tags);
+                if rumor.kind == Kind::PrivateDirectMessage
+                    && peers.contains(&sender)
+                    && rumor.created_at.as_secs() >= since
+                    && expires_at.is_none_or(|t| t > now)
+                {
+                    ret.push(Envelope {
+                        id: event.id.to_hex(),
+                        sender: sender.to_hex(),
+                        created_at: rumor.created_at.as_secs(),
+                        expires_at,
+                        content: rumor.content,
+                    });
+                }
+            }
+        }
+        ret.sort_by_key(|envelope| envelope.created_at);
+
+        Ok(ret)
+    }
+}
@@ -0,0 +1,102 @@
+use bdk_chain::bitcoin;
+use bitcoin::{consensus, hashes::Hash, BlockHash, Transaction};
+use tokio::sync::mpsc;
+
+use crate::Error;
+
+/// Topic of bitcoind `zmqpubhashblock` notifications.
+const TOPIC_HASHBLOCK: &[u8] = b"hashblock";
+/// Topic of bitcoind `zmqpubrawtx` notifications.
+const TOPIC_RAWTX: &[u8] = b"rawtx";
+
+/// Capacity of the notification stream.
+const STREAM_CAPACITY: usize = 100;
+
+/// A notification published by bitcoind.
+#[derive(Debug, Clone)]
+pub enum Notification {
+    /// The hash of a block connected to the best chain
+    Block(BlockHash),
+    /// A transaction that entered the mempool or was connected in a block
+    Tx(Transaction),
+}
+
+/// Subscribes to the `hashblock` and `rawtx` topics of bitcoind's ZMQ interface.
+pub struct ZmqSubscriber {
+    socket: zmq::Socket,
+}
+
+impl std::fmt::Debug for ZmqSubscriber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZmqSubscriber").finish_non_exhaustive()
+    }
+}
+
+impl ZmqSubscriber {
+    /// Connect to each of the given `endpoints`, e.g. `tcp://127.0.0.1:28332`.
+    ///
+    /// bitcoind may publish `hashblock` and `rawtx` on the same or on separate endpoints.
+    pub fn connect<'a>(endpoints: impl IntoIterator<Item = &'a str>) -> Result<Self, Error> {
+        let ctx = zmq::Context::new();
+        let socket = ctx.socket(zmq::SUB).map_err(Error::Zmq)?;
+        for endpoint in endpoints {
+            socket.connect(endpoint).map_err(Error::Zmq)?;
+        }
+        socket.set_subscribe(TOPIC_HASHBLOCK).map_err(Error::Zmq)?;
+        socket.set_subscribe(TOPIC_RAWTX).map_err(Error::Zmq)?;
+
+        Ok(Self { socket })
+    }
+
+    /// Block until the next notification is received.
+    ///
+    /// Messages of an unknown topic or that fail to decode are skipped.
+    pub fn recv(&self) -> Result<Notification, Error> {
+        loop {
+            // A message is made of the topic, the body, and a sequence number.
+            let msg = self.socket.recv_multipart(0).map_err(Error::Zmq)?;
+            let (Some(topic), Some(body)) = (msg.first(), msg.get(1)) else {
+                continue;
+            };
+            match topic.as_slice() {
+                TOPIC_HASHBLOCK => {
+                    // The hash is published in the byte order used for display.
+                    let Ok(mut bytes) = <[u8; 32]>::try_from(body.as_slice()) else {
+                        continue;
+                    };
+                    bytes.reverse();
+                    return Ok(Notification::Block(BlockHash::from_byte_array(bytes)));
+                }
+                TOPIC_RAWTX => {
+                    if let Ok(tx) = consensus::deserialize::<Transaction>(body) {
+                        return Ok(Notification::Tx(tx));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Receive notifications on a background thread, returning the receiving end of the
+    /// stream. The stream ends after the first error.
+    pub fn stream(self) -> mpsc::Receiver<Result<Notification, Error>> {
+        let (tx, rx) = mpsc::channel(STREAM_CAPACITY);
+        std::thread::spawn(move || loop {
+            let res = self.recv();
+            let is_err = res.is_err();
+            if tx.blocking_send(res).is_err() || is_err {
+                break;
+            }
+        });
+
+        rx
+    }
+}
+
+impl Iterator for ZmqSubscriber {
+    type Item = Result<Notification, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.recv())
+    }
+}
@@ -0,0 +1,39 @@
+use nostr_relay_builder::{LocalRelay, RelayBuilder};
+use nostr_sdk::{Client, Keys, RelayUrl};
+
+use crate::Error;
+
+/// An in-process NIP-01 relay listening on localhost and keeping events in memory, for
+/// development and integration tests without network access.
+///
+/// The relay shuts down when dropped. Give each simulated participant a [`TestRelay::client`].
+#[derive(Debug, Clone)]
+pub struct TestRelay {
+    relay: LocalRelay,
+}
+
+impl TestRelay {
+    /// Run a new relay on a random local port.
+    pub async fn run() -> Result<Self, Error> {
+        let relay = LocalRelay::run(RelayBuilder::default())
+            .await
+            .map_err(|e| Error::Messenger(e.to_string()))?;
+
+        Ok(Self { relay })
+    }
+
+    /// Url of the relay, e.g. `ws://127.0.0.1:<port>`.
+    pub fn url(&self) -> RelayUrl {
+        self.relay.url()
+    }
+
+    /// New nostr client signing with `keys`, connected to this relay only. The client can be
+    /// used to build a [`Coordinator`](crate::Coordinator) of a simulated participant.
+    pub async fn client(&self, keys: Keys) -> Result<Client, Error> {
+        let client = Client::builder().signer(keys).build();
+        client.add_relay(self.url()).await.map_err(Error::Nostr)?;
+        client.connect().await;
+
+        Ok(client)
+    }
+}
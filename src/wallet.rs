@@ -27,7 +27,9 @@ use bdk_tx::{
 };
 
 mod changeset;
+mod event;
 pub use changeset::*;
+pub use event::*;
 
 /// Represents the unique id of a descriptor
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -141,6 +143,21 @@ impl BdkWallet {
         )
     }
 
+    /// Whether any input of `psbt` is an output of the wallet already spent by a confirmed
+    /// transaction, be it the PSBT's own or a conflicting one.
+    pub fn is_psbt_spent(&self, psbt: &Psbt) -> bool {
+        let prevouts = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<Vec<_>>();
+        self.list_indexed_txouts().any(|(_, txo)| {
+            prevouts.contains(&txo.outpoint)
+                && txo.spent_by.is_some_and(|(pos, _)| pos.is_confirmed())
+        })
+    }
+
     /// Retrieve the balance
     pub fn balance(&self) -> Balance {
         let chain = &self.chain;
@@ -242,6 +259,25 @@ impl BdkWallet {
         self.stage((index_changeset, tx_graph_changeset));
     }
 
+    /// Insert an unconfirmed `tx` last seen at `seen_at` if it is relevant to the wallet.
+    ///
+    /// Returns whether the tx was relevant. **You must persist the staged changes**.
+    pub fn apply_unconfirmed_tx(&mut self, tx: Transaction, seen_at: u64) -> bool {
+        let index_changeset = self.index.index_tx(&tx);
+
+        if !self.index.is_tx_relevant(&tx) {
+            self.stage(index_changeset);
+            return false;
+        }
+
+        let txid = tx.compute_txid();
+        let mut tx_graph_changeset = self.tx_graph.insert_tx(tx);
+        tx_graph_changeset.merge(self.tx_graph.insert_seen_at(txid, seen_at));
+        self.stage((index_changeset, tx_graph_changeset));
+
+        true
+    }
+
     /// Apply an [`Update`]. This stages the change to be persisted later.
     ///
     /// Errors if the chain update fails.
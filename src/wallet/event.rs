@@ -0,0 +1,403 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+use bdk_core::{BlockId, ConfirmationBlockTime};
+
+use bdk_chain::{
+    bdk_core,
+    bitcoin::{Amount, OutPoint, Transaction, Txid},
+    ChainPosition,
+};
+
+use super::BdkWallet;
+
+/// A change to the canonical state of the wallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletEvent {
+    /// The previous tip is no longer in the best chain
+    Reorg {
+        /// old tip
+        old_tip: BlockId,
+        /// new tip
+        new_tip: BlockId,
+    },
+    /// A new transaction pays the wallet a net positive amount
+    PaymentReceived {
+        /// txid
+        txid: Txid,
+        /// amount received less amount sent
+        amount: Amount,
+    },
+    /// A transaction is confirmed, or confirmed in a different block than before
+    TxConfirmed {
+        /// txid
+        txid: Txid,
+        /// confirmation block
+        anchor: ConfirmationBlockTime,
+    },
+    /// A previously confirmed transaction is now unconfirmed
+    TxUnconfirmed {
+        /// txid
+        txid: Txid,
+    },
+    /// A transaction was replaced by one or more conflicting transactions
+    TxReplaced {
+        /// txid
+        txid: Txid,
+        /// txids of the conflicting transactions
+        replaced_by: Vec<Txid>,
+    },
+    /// A transaction is no longer canonical and no conflict is known
+    TxDropped {
+        /// txid
+        txid: Txid,
+    },
+}
+
+impl fmt::Display for WalletEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reorg { old_tip, new_tip } => write!(
+                f,
+                "Reorg from {} {} to {} {}",
+                old_tip.height, old_tip.hash, new_tip.height, new_tip.hash
+            ),
+            Self::PaymentReceived { txid, amount } => {
+                write!(f, "Payment received {amount} in {txid}")
+            }
+            Self::TxConfirmed { txid, anchor } => {
+                write!(f, "Tx confirmed {txid} at height {}", anchor.block_id.height)
+            }
+            Self::TxUnconfirmed { txid } => write!(f, "Tx unconfirmed {txid}"),
+            Self::TxReplaced { txid, replaced_by } => {
+                let replaced_by: Vec<_> = replaced_by.iter().map(Txid::to_string).collect();
+                write!(f, "Tx replaced {txid} by {}", replaced_by.join(", "))
+            }
+            Self::TxDropped { txid } => write!(f, "Tx dropped {txid}"),
+        }
+    }
+}
+
+/// The canonical state of the wallet at a point in time, from which [`WalletEvent`]s are
+/// derived. See [`BdkWallet::events_since`].
+#[derive(Debug, Clone)]
+pub struct WalletSnapshot {
+    /// chain tip
+    tip: BlockId,
+    /// canonical txs
+    txs: BTreeMap<Txid, (Arc<Transaction>, ChainPosition<ConfirmationBlockTime>)>,
+}
+
+impl BdkWallet {
+    /// Take a snapshot of the canonical state of the wallet.
+    pub fn snapshot(&self) -> WalletSnapshot {
+        WalletSnapshot {
+            tip: self.tip().block_id(),
+            txs: self
+                .transactions()
+                .map(|c| (c.tx_node.txid, (c.tx_node.tx.clone(), c.chain_position)))
+                .collect(),
+        }
+    }
+
+    /// Compare the canonical state of the wallet with an earlier `snapshot`, returning the
+    /// events that describe the difference.
+    pub fn events_since(&self, snapshot: &WalletSnapshot) -> Vec<WalletEvent> {
+        let mut events = vec![];
+
+        let old_tip = snapshot.tip;
+        let new_tip = self.tip().block_id();
+        if old_tip != new_tip {
+            let old_hash = self.tip().get(old_tip.height).map(|cp| cp.hash());
+            if old_hash != Some(old_tip.hash) {
+                events.push(WalletEvent::Reorg { old_tip, new_tip });
+            }
+        }
+
+        let current = self.snapshot().txs;
+
+        for (&txid, (tx, position)) in &current {
+            match (snapshot.txs.get(&txid), position) {
+                (None, _) => {
+                    let (sent, received) = self.index.sent_and_received(tx, ..);
+                    if received > sent {
+                        events.push(WalletEvent::PaymentReceived {
+                            txid,
+                            amount: received - sent,
+                        });
+                    }
+                    if let ChainPosition::Confirmed { anchor, .. } = position {
+                        events.push(WalletEvent::TxConfirmed {
+                            txid,
+                            anchor: *anchor,
+                        });
+                    }
+                }
+                (Some((_, ChainPosition::Confirmed { anchor: old, .. })), position) => {
+                    match position {
+                        ChainPosition::Confirmed { anchor, .. } if anchor != old => {
+                            events.push(WalletEvent::TxConfirmed {
+                                txid,
+                                anchor: *anchor,
+                            });
+                        }
+                        ChainPosition::Unconfirmed { .. } => {
+                            events.push(WalletEvent::TxUnconfirmed { txid });
+                        }
+                        _ => {}
+                    }
+                }
+                (
+                    Some((_, ChainPosition::Unconfirmed { .. })),
+                    ChainPosition::Confirmed { anchor, .. },
+                ) => {
+                    events.push(WalletEvent::TxConfirmed {
+                        txid,
+                        anchor: *anchor,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        // Find the canonical spends of each outpoint so that we can tell which
+        // transactions replaced the ones no longer canonical.
+        let spends: HashMap<OutPoint, Txid> = current
+            .iter()
+            .flat_map(|(&txid, (tx, _))| {
+                tx.input.iter().map(move |txin| (txin.previous_output, txid))
+            })
+            .collect();
+
+        for (&txid, (tx, _)) in &snapshot.txs {
+            if current.contains_key(&txid) {
+                continue;
+            }
+            let replaced_by: BTreeSet<Txid> = tx
+                .input
+                .iter()
+                .filter_map(|txin| spends.get(&txin.previous_output).copied())
+                .collect();
+            if replaced_by.is_empty() {
+                events.push(WalletEvent::TxDropped { txid });
+            } else {
+                events.push(WalletEvent::TxReplaced {
+                    txid,
+                    replaced_by: replaced_by.into_iter().collect(),
+                });
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bdk_chain::bdk_core::CheckPoint;
+    use bdk_chain::bitcoin::{
+        absolute, hashes::Hash, transaction, BlockHash, Network, ScriptBuf, TxIn, TxOut,
+    };
+    use bdk_chain::keychain_txout::KeychainTxOutIndex;
+    use bdk_chain::local_chain::LocalChain;
+    use bdk_chain::miniscript::{Descriptor, DescriptorPublicKey};
+    use bdk_chain::TxGraph;
+
+    use super::*;
+    use crate::{BdkChangeSet, Keychain, Update};
+
+    const DESC: &str = "wpkh([7d94197e/84h/1h/0h]tpubDCmcN1ucMUfxxabEnLKHzUbjaxg8P4YR4V7mMsfhnsdRJquRyDTudrBmzZhrpV4Z4PH3MjKKFtBk6WkJbEWqL9Vc8E8v1tqFxtFXRY8zEjG/0/*)";
+
+    fn hash(n: u8) -> BlockHash {
+        BlockHash::from_byte_array([n; 32])
+    }
+
+    /// Wallet of [`DESC`] with a chain of the genesis block only.
+    fn wallet() -> BdkWallet {
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(DESC).unwrap();
+        let mut index = KeychainTxOutIndex::<Keychain>::default();
+        assert!(index.insert_descriptor(Keychain::EXTERNAL, desc).unwrap());
+        let (chain, _) = LocalChain::from_genesis_hash(hash(0));
+
+        BdkWallet {
+            network: Network::Signet,
+            chain,
+            tx_graph: TxGraph::default(),
+            index,
+            stage: BdkChangeSet::default(),
+        }
+    }
+
+    /// Update the chain to blocks of the given hashes, from genesis up.
+    fn set_chain(wallet: &mut BdkWallet, hashes: &[u8]) {
+        let blocks = hashes.iter().enumerate().map(|(height, &n)| BlockId {
+            height: height as u32,
+            hash: hash(n),
+        });
+        let cp = CheckPoint::from_block_ids(blocks).unwrap();
+        wallet
+            .apply_update(Update {
+                cp: Some(cp),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    /// Transaction spending `prevout` to `value` sats of `script_pubkey`.
+    fn tx(prevout: OutPoint, value: u64, script_pubkey: ScriptBuf) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey,
+            }],
+        }
+    }
+
+    /// Receive `value` sats from a foreign output, seen unconfirmed at `seen_at`.
+    fn receive(wallet: &mut BdkWallet, value: u64, seen_at: u64) -> Txid {
+        let (_, addr) = wallet.reveal_next_address().unwrap();
+        let prevout = OutPoint::new(Txid::from_byte_array([seen_at as u8; 32]), 0);
+        let tx = tx(prevout, value, addr.script_pubkey());
+        let txid = tx.compute_txid();
+        assert!(wallet.apply_unconfirmed_tx(tx, seen_at));
+        txid
+    }
+
+    /// Spend the first output of `txid` to a foreign script, seen unconfirmed at `seen_at`.
+    fn spend(wallet: &mut BdkWallet, txid: Txid, value: u64, seen_at: u64) -> Txid {
+        let tx = tx(OutPoint::new(txid, 0), value, ScriptBuf::new());
+        let txid = tx.compute_txid();
+        assert!(wallet.apply_unconfirmed_tx(tx, seen_at));
+        txid
+    }
+
+    /// Confirm `txid` in the block of the local chain at `height`.
+    fn confirm(wallet: &mut BdkWallet, txid: Txid, height: u32) -> ConfirmationBlockTime {
+        let anchor = ConfirmationBlockTime {
+            block_id: wallet.tip().get(height).unwrap().block_id(),
+            confirmation_time: 100 + u64::from(height),
+        };
+        let changeset = wallet.tx_graph.insert_anchor(txid, anchor);
+        wallet.stage(changeset);
+        anchor
+    }
+
+    #[test]
+    fn payment_received() {
+        let mut wallet = wallet();
+        let snapshot = wallet.snapshot();
+        let txid = receive(&mut wallet, 10_000, 1);
+        assert_eq!(
+            wallet.events_since(&snapshot),
+            vec![WalletEvent::PaymentReceived {
+                txid,
+                amount: Amount::from_sat(10_000),
+            }]
+        );
+        // nothing new since
+        assert_eq!(wallet.events_since(&wallet.snapshot()), vec![]);
+    }
+
+    #[test]
+    fn tx_confirmed() {
+        let mut wallet = wallet();
+        let txid = receive(&mut wallet, 10_000, 1);
+        set_chain(&mut wallet, &[0, 1]);
+        let snapshot = wallet.snapshot();
+        let anchor = confirm(&mut wallet, txid, 1);
+        assert_eq!(
+            wallet.events_since(&snapshot),
+            vec![WalletEvent::TxConfirmed { txid, anchor }]
+        );
+    }
+
+    #[test]
+    fn new_confirmed_payment() {
+        let mut wallet = wallet();
+        set_chain(&mut wallet, &[0, 1]);
+        let snapshot = wallet.snapshot();
+        let txid = receive(&mut wallet, 10_000, 1);
+        let anchor = confirm(&mut wallet, txid, 1);
+        assert_eq!(
+            wallet.events_since(&snapshot),
+            vec![
+                WalletEvent::PaymentReceived {
+                    txid,
+                    amount: Amount::from_sat(10_000),
+                },
+                WalletEvent::TxConfirmed { txid, anchor },
+            ]
+        );
+    }
+
+    #[test]
+    fn reorg_unconfirms_tx() {
+        let mut wallet = wallet();
+        set_chain(&mut wallet, &[0, 1]);
+        let txid = receive(&mut wallet, 10_000, 1);
+        confirm(&mut wallet, txid, 1);
+        let snapshot = wallet.snapshot();
+        let old_tip = wallet.tip().block_id();
+
+        // block 1 is replaced
+        set_chain(&mut wallet, &[0, 2]);
+        assert_eq!(
+            wallet.events_since(&snapshot),
+            vec![
+                WalletEvent::Reorg {
+                    old_tip,
+                    new_tip: wallet.tip().block_id(),
+                },
+                WalletEvent::TxUnconfirmed { txid },
+            ]
+        );
+    }
+
+    #[test]
+    fn new_tip_is_not_a_reorg() {
+        let mut wallet = wallet();
+        set_chain(&mut wallet, &[0, 1]);
+        let snapshot = wallet.snapshot();
+        set_chain(&mut wallet, &[0, 1, 2]);
+        assert_eq!(wallet.events_since(&snapshot), vec![]);
+    }
+
+    #[test]
+    fn tx_replaced() {
+        let mut wallet = wallet();
+        let received = receive(&mut wallet, 10_000, 1);
+        let txid = spend(&mut wallet, received, 9_000, 2);
+        let snapshot = wallet.snapshot();
+
+        // seen later, so it wins the conflict
+        let replacement = spend(&mut wallet, received, 8_000, 3);
+        assert_eq!(
+            wallet.events_since(&snapshot),
+            vec![WalletEvent::TxReplaced {
+                txid,
+                replaced_by: vec![replacement],
+            }]
+        );
+    }
+
+    #[test]
+    fn tx_dropped() {
+        let mut wallet = wallet();
+        let txid = receive(&mut wallet, 10_000, 1);
+        let snapshot = wallet.snapshot();
+
+        // evicted from the mempool with no conflict
+        let changeset = wallet.tx_graph.insert_evicted_at(txid, 2);
+        wallet.stage(changeset);
+        assert_eq!(wallet.events_since(&snapshot), vec![WalletEvent::TxDropped { txid }]);
+    }
+}
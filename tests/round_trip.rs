@@ -0,0 +1,74 @@
+#![cfg(feature = "test-relay")]
+
+use std::sync::Arc;
+
+use loon::nostr_sdk::Keys;
+use loon::{
+    Call, CallPayload, CallTy, Messenger, NostrMessenger, Opened, Pid, Reassembler, TestRelay,
+    Transport, CHUNK_SIZE,
+};
+
+/// Fingerprint of the quorum of the simulated participants.
+const FINGERPRINT: &str = "0badf00d";
+
+/// Alice sends a note with `text` to Bob over a local relay, and Bob fetches, decrypts and
+/// reassembles it.
+async fn call_new_then_fetch(transport: Transport, text: String) {
+    let relay = TestRelay::run().await.unwrap();
+    let (alice, bob) = (Keys::generate(), Keys::generate());
+    let (alice_id, bob_id) = (Pid::from(1), Pid::from(2));
+
+    let payload = CallPayload::new(CallTy::Note { text });
+    let calls = Call::seal(&alice, FINGERPRINT, &payload, &[(bob_id, bob.public_key())])
+        .await
+        .unwrap();
+    let client = Arc::new(relay.client(alice.clone()).await.unwrap());
+    let messenger = NostrMessenger::new(client, transport).await.unwrap();
+    for call in &calls {
+        let call = call.to_string();
+        messenger.send(&bob.public_key().to_hex(), &call, None).await.unwrap();
+    }
+
+    let client = Arc::new(relay.client(bob.clone()).await.unwrap());
+    let messenger = NostrMessenger::new(client, transport).await.unwrap();
+    let envelopes = messenger.fetch_since(&[alice.public_key().to_hex()], 0).await.unwrap();
+    assert_eq!(envelopes.len(), calls.len());
+
+    let mut reassembler = Reassembler::default();
+    let mut messages = vec![];
+    for envelope in &envelopes {
+        assert_eq!(envelope.sender, alice.public_key().to_hex());
+        let opened = Call::open(&bob, FINGERPRINT, bob_id, &alice.public_key(), &envelope.content)
+            .await
+            .unwrap();
+        let Opened::Chunk(chunk) = opened else {
+            panic!("expected a chunk, got {opened:?}");
+        };
+        let message = reassembler
+            .insert(&envelope.sender, chunk, payload.created_at)
+            .unwrap();
+        messages.extend(message);
+    }
+    assert_eq!(reassembler.pending(), 0);
+    assert_eq!(messages.len(), 1);
+    let received = CallPayload::decode(&messages[0], payload.created_at).unwrap();
+    assert_eq!(received, payload);
+
+    // the call is only for Bob, and only for this quorum
+    let call = envelopes[0].content.as_str();
+    let opened = Call::open(&alice, FINGERPRINT, alice_id, &bob.public_key(), call).await;
+    assert_eq!(opened, Ok(Opened::NotForUs));
+    let opened = Call::open(&bob, "f00dfeed", bob_id, &alice.public_key(), call).await;
+    assert_eq!(opened, Ok(Opened::OtherQuorum(FINGERPRINT.to_string())));
+}
+
+#[tokio::test]
+async fn call_new_then_fetch_note() {
+    // long enough to be sent in parts
+    call_new_then_fetch(Transport::Note, "a".repeat(CHUNK_SIZE + 1)).await;
+}
+
+#[tokio::test]
+async fn call_new_then_fetch_gift_wrap() {
+    call_new_then_fetch(Transport::GiftWrap, "hello".to_string()).await;
+}